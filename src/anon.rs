@@ -0,0 +1,269 @@
+//! `--anonymize`: replace the file paths in the output's headers with `file1`, `file2`, … so a
+//! patch's structure can be shared without its internal paths, and top-level directories (which
+//! name `--split-by-dir` patches) with `dir1`, `dir2`, …. Placeholders are assigned once over
+//! the whole input, so they stay the same from one save to the next, and `--anonymize-map`
+//! writes them down for turning the patch back.
+
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::{FileDiff, strip_timestamp};
+
+pub struct Anonymizer {
+    placeholders: HashMap<String, String>,
+    // Real paths in the order their placeholders were handed out
+    order: Vec<String>,
+    // Top-level directories and theirs, in the same way
+    dirs: HashMap<String, String>,
+    dir_order: Vec<String>,
+}
+
+impl Anonymizer {
+    pub fn new(files: &[FileDiff]) -> Self {
+        let mut anon = Anonymizer {
+            placeholders: HashMap::new(),
+            order: Vec::new(),
+            dirs: HashMap::new(),
+            dir_order: Vec::new(),
+        };
+        for file in files {
+            // `diff --git` lines last: their paths are split by the ones named elsewhere
+            let (git, rest): (Vec<&String>, Vec<&String>) = file
+                .headers
+                .iter()
+                .partition(|l| l.starts_with("diff --git "));
+            let mut seen: Vec<String> = Vec::new();
+            let mut note = |path: &str| {
+                seen.push(path.to_string());
+                anon.assign(path)
+            };
+            for line in rest {
+                rewrite(line, &mut note, &|_| false);
+            }
+            for line in git {
+                rewrite(line, &mut |path| anon.assign(path), &|p| {
+                    seen.iter().any(|s| s == p)
+                });
+            }
+            if let Some((dir, _)) = file.path().split_once('/')
+                && !anon.dirs.contains_key(dir)
+            {
+                let placeholder = format!("dir{}", anon.dir_order.len() + 1);
+                anon.dir_order.push(dir.to_string());
+                anon.dirs.insert(dir.to_string(), placeholder);
+            }
+        }
+        anon
+    }
+
+    fn assign(&mut self, path: &str) -> String {
+        if let Some(p) = self.placeholders.get(path) {
+            return p.clone();
+        }
+        let placeholder = format!("file{}", self.order.len() + 1);
+        self.order.push(path.to_string());
+        self.placeholders
+            .insert(path.to_string(), placeholder.clone());
+        placeholder
+    }
+
+    /// `line` with the paths it names replaced; lines that name none come back unchanged.
+    pub fn header(&self, line: &str) -> String {
+        rewrite(line, &mut |path| self.path(path), &|p| {
+            self.placeholders.contains_key(p)
+        })
+        .unwrap_or_else(|| line.to_string())
+    }
+
+    /// The placeholder of a path (without `a/`/`b/`), as written in the headers.
+    pub fn path(&self, path: &str) -> String {
+        // Every header line went through `new`, so every path has a placeholder
+        self.placeholders
+            .get(path)
+            .cloned()
+            .unwrap_or_else(|| "file".to_string())
+    }
+
+    /// The placeholder of a top-level directory.
+    pub fn dir(&self, dir: &str) -> String {
+        self.dirs
+            .get(dir)
+            .cloned()
+            .unwrap_or_else(|| "dir".to_string())
+    }
+
+    /// Write the placeholder of each path, one `PLACEHOLDER<TAB>PATH` per line.
+    pub fn write_map(&self, path: &Path) -> Result<()> {
+        let mut out = String::from("# patchers anonymize map: <placeholder>\t<path>\n");
+        for real in &self.order {
+            out.push_str(&format!("{}\t{real}\n", self.placeholders[real]));
+        }
+        for real in &self.dir_order {
+            out.push_str(&format!("{}\t{real}/\n", self.dirs[real]));
+        }
+        fs::write(path, out).with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// Rebuild a header line with each path it names passed through `anon`, keeping git's `a/` and
+/// `b/` prefixes and whatever follows the path (timestamps, revisions). `None` when the line
+/// names no path. `known` tells the paths already seen on the file's other header lines.
+fn rewrite(
+    line: &str,
+    anon: &mut impl FnMut(&str) -> String,
+    known: &impl Fn(&str) -> bool,
+) -> Option<String> {
+    let mut side = |p: &str| {
+        if p == "/dev/null" {
+            return p.to_string();
+        }
+        match p.split_at_checked(2) {
+            Some((prefix @ ("a/" | "b/"), rest)) => format!("{prefix}{}", anon(rest)),
+            _ => anon(p),
+        }
+    };
+    if let Some(rest) = line.strip_prefix("diff --git ") {
+        return Some(match split_git_paths(rest, known) {
+            Some((old, new)) => format!("diff --git {} {}", side(old), side(new)),
+            // No way to tell where one path ends; one placeholder for the lot
+            None => format!("diff --git {}", side(rest)),
+        });
+    }
+    for prefix in ["--- ", "+++ "] {
+        if let Some(rest) = line.strip_prefix(prefix) {
+            let path = strip_timestamp(rest);
+            return Some(format!("{prefix}{}{}", side(path), &rest[path.len()..]));
+        }
+    }
+    for prefix in [
+        "rename from ",
+        "rename to ",
+        "copy from ",
+        "copy to ",
+        "Index: ",
+        "Property changes on: ",
+    ] {
+        if let Some(path) = line.strip_prefix(prefix) {
+            return Some(format!("{prefix}{}", anon(path)));
+        }
+    }
+    if line.starts_with("diff -r ") {
+        let (revs, path) = line.rsplit_once(' ')?;
+        return Some(format!("{revs} {}", anon(path)));
+    }
+    let files = line
+        .strip_prefix("Binary files ")?
+        .strip_suffix(" differ")?;
+    let (old, new) = files.split_once(" and ")?;
+    Some(format!(
+        "Binary files {} and {} differ",
+        side(old),
+        side(new)
+    ))
+}
+
+/// The two paths of a `diff --git` line, which may contain spaces themselves: split at the
+/// space that leaves two paths `known` from the file's other headers, or else two equal ones
+/// (a file changed in place), as git does.
+fn split_git_paths<'a>(rest: &'a str, known: &impl Fn(&str) -> bool) -> Option<(&'a str, &'a str)> {
+    let bare = |p: &'a str| match p.split_at_checked(2) {
+        Some(("a/" | "b/", rest)) => rest,
+        _ => p,
+    };
+    let splits = || {
+        rest.match_indices(' ')
+            .map(|(i, _)| (&rest[..i], &rest[i + 1..]))
+    };
+    splits()
+        .find(|&(old, new)| known(bare(old)) && known(bare(new)))
+        .or_else(|| splits().find(|&(old, new)| bare(old) == bare(new)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_unified_diff;
+    use regex::Regex;
+
+    const GIT: &str = "\
+diff --git a/old name.txt b/new name.txt
+similarity index 90%
+rename from old name.txt
+rename to new name.txt
+--- a/old name.txt
++++ b/new name.txt
+@@ -1 +1 @@
+-x
++y
+diff --git a/src/a.c b/lib/b.c
+similarity index 100%
+copy from src/a.c
+copy to lib/b.c
+diff --git a/img.png b/img.png
+index 1111111..2222222 100644
+Binary files a/img.png and b/img.png differ
+diff --git a/my file.sh b/my file.sh
+old mode 100644
+new mode 100755
+";
+
+    // Plain `diff -u`, timestamps after a tab
+    const PLAIN: &str = "\
+--- old/notes.txt\t2024-01-01 10:00:00.000000000 +0100
++++ new/notes.txt\t2024-01-02 11:00:00.000000000 +0100
+@@ -1 +1 @@
+-x
++y
+";
+
+    /// Anonymize every header line of `patch`, check no real path is left, and turn the lines
+    /// back with the map.
+    fn round_trip(patch: &str) {
+        let (files, _) = parse_unified_diff(patch).unwrap();
+        let anon = Anonymizer::new(&files);
+        let placeholder = Regex::new(r"\bfile\d+\b").unwrap();
+        let real: HashMap<&String, &String> =
+            anon.placeholders.iter().map(|(r, p)| (p, r)).collect();
+        for line in files.iter().flat_map(|f| &f.headers) {
+            let hidden = anon.header(line);
+            for path in &anon.order {
+                assert!(!hidden.contains(path.as_str()), "{path} left in {hidden}");
+            }
+            let back = placeholder.replace_all(&hidden, |c: &regex::Captures| {
+                real[&c[0].to_string()].clone()
+            });
+            assert_eq!(back, *line);
+        }
+    }
+
+    #[test]
+    fn headers_round_trip_through_the_map() {
+        round_trip(GIT);
+        round_trip(PLAIN);
+        round_trip(include_str!("../fixtures/svn.patch"));
+        round_trip(include_str!("../fixtures/hg.patch"));
+    }
+
+    #[test]
+    fn git_lines_with_spaces_split_where_the_other_headers_say() {
+        let (files, _) = parse_unified_diff(GIT).unwrap();
+        let anon = Anonymizer::new(&files);
+        let old = anon.path("old name.txt");
+        let new = anon.path("new name.txt");
+        assert_ne!(old, new);
+        assert_eq!(
+            anon.header("diff --git a/old name.txt b/new name.txt"),
+            format!("diff --git a/{old} b/{new}")
+        );
+        // Without `---`/`+++` lines, a file changed in place still splits in the middle
+        let same = anon.path("my file.sh");
+        assert_eq!(
+            anon.header("diff --git a/my file.sh b/my file.sh"),
+            format!("diff --git a/{same} b/{same}")
+        );
+        // Split patches go by the new path's directory
+        assert_eq!(anon.dir("lib"), "dir1");
+    }
+}
@@ -0,0 +1,187 @@
+//! Block view of a hunk: each run of removed lines is followed by the lines that replace it,
+//! paired up so word-level changes can be highlighted, and long stretches of context between
+//! blocks are folded.
+
+use similar::{ChangeTag, TextDiff};
+
+/// Context lines kept on each side of a change block; longer runs are folded.
+pub const NEIGHBORHOOD: usize = 3;
+
+/// One row of the block view, referring to hunk body lines by index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Row {
+    /// A body line shown as it is.
+    Line(usize),
+    /// A removed line and the added line aligned with it.
+    Pair(usize, usize),
+    /// This many unchanged lines left out.
+    Fold(usize),
+}
+
+fn is_change(l: &str) -> bool {
+    l.starts_with('-') || l.starts_with('+')
+}
+
+/// Lay out a hunk body as block-view rows.
+pub fn rows(lines: &[String]) -> Vec<Row> {
+    let mut rows = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        if is_change(&lines[i]) {
+            let (mut dels, mut adds, mut markers) = (Vec::new(), Vec::new(), Vec::new());
+            while i < lines.len() && !lines[i].starts_with('+') && !lines[i].starts_with(' ') {
+                if lines[i].starts_with('-') {
+                    dels.push(i);
+                } else if lines[i].starts_with('\\') {
+                    markers.push(i);
+                } else {
+                    break;
+                }
+                i += 1;
+            }
+            while i < lines.len() && (lines[i].starts_with('+') || lines[i].starts_with('\\')) {
+                if lines[i].starts_with('+') {
+                    adds.push(i);
+                } else {
+                    markers.push(i);
+                }
+                i += 1;
+            }
+            for k in 0..dels.len().max(adds.len()) {
+                rows.push(match (dels.get(k), adds.get(k)) {
+                    (Some(&d), Some(&a)) => Row::Pair(d, a),
+                    (Some(&d), None) => Row::Line(d),
+                    (None, Some(&a)) => Row::Line(a),
+                    (None, None) => unreachable!(),
+                });
+            }
+            rows.extend(markers.into_iter().map(Row::Line));
+        } else {
+            let start = i;
+            while i < lines.len() && !is_change(&lines[i]) {
+                i += 1;
+            }
+            let keep_before = if start == 0 { 0 } else { NEIGHBORHOOD };
+            let keep_after = if i == lines.len() { 0 } else { NEIGHBORHOOD };
+            let run = i - start;
+            if run > keep_before + keep_after + 1 {
+                rows.extend((start..start + keep_before).map(Row::Line));
+                rows.push(Row::Fold(run - keep_before - keep_after));
+                rows.extend((i - keep_after..i).map(Row::Line));
+            } else {
+                rows.extend((start..i).map(Row::Line));
+            }
+        }
+    }
+    rows
+}
+
+/// Lay out only the changed lines of a hunk (with their `\ No newline` markers): context
+/// between two blocks becomes a single fold, context before the first and after the last is
+/// dropped.
+pub fn compact_rows(lines: &[String]) -> Vec<Row> {
+    let mut rows = Vec::new();
+    let mut skipped = 0;
+    let mut shown_last = false;
+    for (i, l) in lines.iter().enumerate() {
+        let keep = is_change(l) || (l.starts_with('\\') && shown_last);
+        if keep {
+            if skipped > 0 && !rows.is_empty() {
+                rows.push(Row::Fold(skipped));
+            }
+            skipped = 0;
+            rows.push(Row::Line(i));
+        } else {
+            skipped += 1;
+        }
+        shown_last = keep;
+    }
+    rows
+}
+
+/// Body line indices where each run of `+`/`-` lines starts.
+pub fn change_starts(lines: &[String]) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut in_run = false;
+    for (i, l) in lines.iter().enumerate() {
+        let change = is_change(l);
+        // A "\ No newline" marker doesn't end a run
+        if change && !in_run {
+            starts.push(i);
+        }
+        if !l.starts_with('\\') {
+            in_run = change;
+        }
+    }
+    starts
+}
+
+/// Pieces of a line, each flagged when it differs from the line it's paired with.
+pub type Segments<'a> = Vec<(bool, &'a str)>;
+
+/// Split an old and a new line (without their `-`/`+`) into segments, flagging the words that
+/// differ between them.
+pub fn word_diff<'a>(old: &'a str, new: &'a str) -> (Segments<'a>, Segments<'a>) {
+    let (mut old_parts, mut new_parts) = (Vec::new(), Vec::new());
+    for change in TextDiff::from_words(old, new).iter_all_changes() {
+        let part = change.value();
+        match change.tag() {
+            ChangeTag::Equal => {
+                old_parts.push((false, part));
+                new_parts.push((false, part));
+            }
+            ChangeTag::Delete => old_parts.push((true, part)),
+            ChangeTag::Insert => new_parts.push((true, part)),
+        }
+    }
+    (old_parts, new_parts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|l| l.to_string()).collect()
+    }
+
+    #[test]
+    fn compact_rows_fold_the_context_between_blocks_only() {
+        let lines = body(&[" a", " b", "-c", "+C", " d", " e", " f", "+g", " h"]);
+        assert_eq!(
+            compact_rows(&lines),
+            [Row::Line(2), Row::Line(3), Row::Fold(3), Row::Line(7)]
+        );
+        // Touching blocks need no fold, and a body without changes shows nothing
+        let lines = body(&["-a", "+b", "-c"]);
+        assert_eq!(
+            compact_rows(&lines),
+            [Row::Line(0), Row::Line(1), Row::Line(2)]
+        );
+        assert_eq!(compact_rows(&body(&[" a", " b"])), []);
+    }
+
+    #[test]
+    fn compact_rows_keep_no_newline_markers_of_shown_lines() {
+        let marker = "\\ No newline at end of file";
+        let lines = body(&["-a", marker, "+b", marker]);
+        assert_eq!(
+            compact_rows(&lines),
+            [Row::Line(0), Row::Line(1), Row::Line(2), Row::Line(3)]
+        );
+        // After context that isn't shown, the marker goes too
+        let lines = body(&["+a", " b", marker]);
+        assert_eq!(compact_rows(&lines), [Row::Line(0)]);
+    }
+
+    #[test]
+    fn change_starts_finds_each_run_of_changes() {
+        let marker = "\\ No newline at end of file";
+        let lines = body(&[" a", "-b", "+B", " c", "+d", " e", "-f"]);
+        assert_eq!(change_starts(&lines), [1, 4, 6]);
+        // A marker inside a run doesn't split it; one after context doesn't start a run
+        let lines = body(&["-a", marker, "+A", marker, " b", marker]);
+        assert_eq!(change_starts(&lines), [0]);
+        assert_eq!(change_starts(&body(&[" a", " b"])), []);
+    }
+}
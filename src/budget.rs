@@ -0,0 +1,173 @@
+//! Render budget: when frames get slow (high-latency SSH, busy tmux), trade fidelity for speed.
+//!
+//! The ladder is a plain function from measured frame cost to the features to draw, so every
+//! step is explicit; [`RenderBudget`] only smooths the measurements and applies hysteresis.
+
+use std::time::Duration;
+
+/// What the draw code is allowed to spend time on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RenderFeatures {
+    /// Ladder step, 0 = full fidelity.
+    pub level: u8,
+    /// Color `+`/`-` lines in the preview.
+    pub highlight: bool,
+    /// Cap on preview body lines per hunk.
+    pub preview_limit: Option<usize>,
+    pub scrollbar: bool,
+    /// One short column per list row instead of label + preview.
+    pub compact: bool,
+}
+
+pub const FULL: RenderFeatures = RenderFeatures {
+    level: 0,
+    highlight: true,
+    preview_limit: None,
+    scrollbar: true,
+    compact: false,
+};
+
+/// Frame cost at which each further step of the ladder kicks in.
+const LADDER: [Duration; 4] = [
+    Duration::from_millis(20),
+    Duration::from_millis(40),
+    Duration::from_millis(80),
+    Duration::from_millis(150),
+];
+
+const SHORT_PREVIEW: usize = 40;
+
+/// The degradation ladder: highlighting goes first, then long previews, the scrollbar, and
+/// finally the list drops to compact rows.
+pub fn features_for_cost(cost: Duration) -> RenderFeatures {
+    let level = LADDER.iter().filter(|&&t| cost >= t).count() as u8;
+    RenderFeatures {
+        level,
+        highlight: level < 1,
+        preview_limit: (level >= 2).then_some(SHORT_PREVIEW),
+        scrollbar: level < 3,
+        compact: level >= 4,
+    }
+}
+
+/// Tracks recent frame times and picks the features for the next frame.
+pub struct RenderBudget {
+    /// Exponential moving average of draw time.
+    avg: Duration,
+    current: RenderFeatures,
+    /// When off, always draw at full fidelity.
+    pub enabled: bool,
+}
+
+impl RenderBudget {
+    pub fn new() -> Self {
+        Self {
+            avg: Duration::ZERO,
+            current: FULL,
+            enabled: true,
+        }
+    }
+
+    pub fn features(&self) -> RenderFeatures {
+        if self.enabled { self.current } else { FULL }
+    }
+
+    /// Feed the duration of the last frame. Degrading happens at once; recovering needs the
+    /// average to fall a step below the threshold so the UI doesn't flicker between levels.
+    pub fn record(&mut self, frame: Duration) {
+        self.avg = (self.avg * 3 + frame) / 4;
+        let wanted = features_for_cost(self.avg);
+        if wanted.level > self.current.level {
+            self.current = wanted;
+        } else if wanted.level < self.current.level {
+            let relaxed = features_for_cost(self.avg * 2);
+            if relaxed.level < self.current.level {
+                self.current = relaxed;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(n: u64) -> Duration {
+        Duration::from_millis(n)
+    }
+
+    #[test]
+    fn each_ladder_step_turns_off_one_more_feature() {
+        assert_eq!(features_for_cost(ms(19)), FULL);
+
+        let step1 = features_for_cost(ms(20));
+        assert_eq!(step1.level, 1);
+        assert_eq!(
+            step1,
+            RenderFeatures {
+                level: 1,
+                highlight: false,
+                ..FULL
+            }
+        );
+
+        let step2 = features_for_cost(ms(40));
+        assert_eq!(
+            step2,
+            RenderFeatures {
+                level: 2,
+                preview_limit: Some(SHORT_PREVIEW),
+                ..step1
+            }
+        );
+
+        let step3 = features_for_cost(ms(80));
+        assert_eq!(
+            step3,
+            RenderFeatures {
+                level: 3,
+                scrollbar: false,
+                ..step2
+            }
+        );
+
+        let step4 = features_for_cost(ms(150));
+        assert_eq!(
+            step4,
+            RenderFeatures {
+                level: 4,
+                compact: true,
+                ..step3
+            }
+        );
+        assert_eq!(features_for_cost(Duration::from_secs(10)), step4);
+    }
+
+    #[test]
+    fn degrades_at_once_and_recovers_below_half_the_threshold() {
+        let mut budget = RenderBudget::new();
+        // One slow frame lifts the average to 25ms, past the first step
+        budget.record(ms(100));
+        assert_eq!(budget.avg, ms(25));
+        assert_eq!(budget.features().level, 1);
+
+        // Fast frames pull the average down, but level 1 holds until it drops below 10ms
+        let mut frames = 0;
+        while budget.features().level == 1 {
+            assert!(budget.avg >= ms(10), "kept level 1 at {:?}", budget.avg);
+            budget.record(ms(1));
+            frames += 1;
+        }
+        assert!(frames > 1);
+        assert!(budget.avg < ms(10));
+        assert_eq!(budget.features(), FULL);
+    }
+
+    #[test]
+    fn disabled_budget_always_draws_everything() {
+        let mut budget = RenderBudget::new();
+        budget.enabled = false;
+        budget.record(Duration::from_secs(1));
+        assert_eq!(budget.features(), FULL);
+    }
+}
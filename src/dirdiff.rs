@@ -0,0 +1,277 @@
+//! `--dir-diff A B`: build the input by comparing two directory trees, so patchers works as a
+//! patch builder without git or a ready-made patch.
+//!
+//! The result is a git-style patch: `diff --git a/PATH b/PATH` sections with `new file mode`
+//! and `deleted file mode` lines for files only on one side, `old mode`/`new mode` lines for
+//! files that became (or stopped being) executable, and `Binary files … differ` for files that
+//! aren't UTF-8 text. Symlinks are skipped, as are `.git`, `.hg` and `.svn`
+//! directories and anything matching an `--exclude` glob.
+
+use anyhow::{Context, Result, bail};
+use similar::TextDiff;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::expr::glob_match;
+
+const VCS_DIRS: [&str; 3] = [".git", ".hg", ".svn"];
+
+/// Diff every file under `a` against the file at the same path under `b`.
+pub fn diff_dirs(a: &Path, b: &Path, exclude: &[String]) -> Result<String> {
+    for dir in [a, b] {
+        if !dir.is_dir() {
+            bail!("--dir-diff: {} is not a directory", dir.display());
+        }
+    }
+    let mut paths = BTreeSet::new();
+    walk(a, "", exclude, &mut paths)?;
+    walk(b, "", exclude, &mut paths)?;
+
+    let mut out = String::new();
+    for path in &paths {
+        let old = read(&a.join(path))?;
+        let new = read(&b.join(path))?;
+        if old == new {
+            continue;
+        }
+        out.push_str(&format!("diff --git a/{path} b/{path}\n"));
+        match (&old, &new) {
+            (None, Some((_, mode))) => out.push_str(&format!("new file mode {mode}\n")),
+            (Some((_, mode)), None) => out.push_str(&format!("deleted file mode {mode}\n")),
+            (Some((old_bytes, old_mode)), Some((new_bytes, new_mode))) => {
+                if old_mode != new_mode {
+                    out.push_str(&format!("old mode {old_mode}\nnew mode {new_mode}\n"));
+                }
+                // Only the mode changed
+                if old_bytes == new_bytes {
+                    continue;
+                }
+            }
+            (None, None) => {}
+        }
+        let old = old.map(|(bytes, _)| bytes);
+        let new = new.map(|(bytes, _)| bytes);
+        let side = |contents: &Option<Vec<u8>>, prefix: &str| match contents {
+            Some(_) => format!("{prefix}/{path}"),
+            None => "/dev/null".to_string(),
+        };
+        let (old_name, new_name) = (side(&old, "a"), side(&new, "b"));
+        match (as_text(&old), as_text(&new)) {
+            (Some(old_text), Some(new_text)) => out.push_str(
+                &TextDiff::from_lines(old_text, new_text)
+                    .unified_diff()
+                    .header(&old_name, &new_name)
+                    .to_string(),
+            ),
+            _ => out.push_str(&format!("Binary files {old_name} and {new_name} differ\n")),
+        }
+    }
+    Ok(out)
+}
+
+/// A file's contents as text (empty for a missing file); `None` for binary files, which are
+/// told apart the way git does it, by a NUL byte, or by not being UTF-8.
+fn as_text(contents: &Option<Vec<u8>>) -> Option<&str> {
+    match contents {
+        Some(bytes) if bytes.contains(&0) => None,
+        Some(bytes) => std::str::from_utf8(bytes).ok(),
+        None => Some(""),
+    }
+}
+
+/// Collect the `/`-separated paths of the regular files under `root/rel`.
+fn walk(root: &Path, rel: &str, exclude: &[String], out: &mut BTreeSet<String>) -> Result<()> {
+    let dir = root.join(rel);
+    let entries =
+        fs::read_dir(&dir).with_context(|| format!("failed to read {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry.with_context(|| format!("failed to read {}", dir.display()))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let path = if rel.is_empty() {
+            name.clone()
+        } else {
+            format!("{rel}/{name}")
+        };
+        if exclude.iter().any(|pattern| glob_match(pattern, &path)) {
+            continue;
+        }
+        let kind = entry.file_type()?;
+        if kind.is_dir() && !VCS_DIRS.contains(&name.as_str()) {
+            walk(root, &path, exclude, out)?;
+        } else if kind.is_file() {
+            out.insert(path);
+        }
+    }
+    Ok(())
+}
+
+/// Contents and git mode of a file, or `None` when it doesn't exist (or isn't a regular file).
+fn read(path: &Path) -> Result<Option<(Vec<u8>, &'static str)>> {
+    if !path.is_file() || path.is_symlink() {
+        return Ok(None);
+    }
+    let meta = fs::metadata(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let bytes = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(Some((bytes, mode(&meta))))
+}
+
+/// The mode git records for a regular file: executable or not.
+#[cfg(unix)]
+fn mode(meta: &fs::Metadata) -> &'static str {
+    use std::os::unix::fs::PermissionsExt;
+    if meta.permissions().mode() & 0o111 != 0 {
+        "100755"
+    } else {
+        "100644"
+    }
+}
+
+#[cfg(not(unix))]
+fn mode(_: &fs::Metadata) -> &'static str {
+    "100644"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fresh tree under the temp dir holding `files` (path, contents).
+    fn tree(name: &str, files: &[(&str, &[u8])]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("patchers-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        for (path, contents) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn added_deleted_modified_and_binary_files() {
+        let a = tree(
+            "dirdiff-a",
+            &[
+                ("same.txt", b"same\n"),
+                ("gone.txt", b"old\n"),
+                ("src/lib.rs", b"one\ntwo\n"),
+                ("logo.png", b"\x89PNG\0a"),
+            ],
+        );
+        let b = tree(
+            "dirdiff-b",
+            &[
+                ("same.txt", b"same\n"),
+                ("new.txt", b"fresh\n"),
+                ("src/lib.rs", b"one\n2\n"),
+                ("logo.png", b"\x89PNG\0b"),
+            ],
+        );
+        let patch = diff_dirs(&a, &b, &[]).unwrap();
+        assert!(!patch.contains("same.txt"));
+        assert!(patch.contains(
+            "diff --git a/gone.txt b/gone.txt\ndeleted file mode 100644\n--- a/gone.txt\n+++ /dev/null\n@@ -1 +0,0 @@\n-old\n"
+        ));
+        assert!(patch.contains(
+            "diff --git a/new.txt b/new.txt\nnew file mode 100644\n--- /dev/null\n+++ b/new.txt\n@@ -0,0 +1 @@\n+fresh\n"
+        ));
+        assert!(patch.contains(
+            "diff --git a/src/lib.rs b/src/lib.rs\n--- a/src/lib.rs\n+++ b/src/lib.rs\n@@ -1,2 +1,2 @@\n one\n-two\n+2\n"
+        ));
+        assert!(patch.contains(
+            "diff --git a/logo.png b/logo.png\nBinary files a/logo.png and b/logo.png differ\n"
+        ));
+        let (files, hunks) = crate::parse_unified_diff(&patch).unwrap();
+        assert_eq!(files.len(), 4);
+        // The binary file is one header-only entry
+        assert_eq!(hunks.len(), 4);
+        assert_eq!(hunks.iter().filter(|h| h.header_only).count(), 1);
+    }
+
+    #[test]
+    fn missing_trailing_newline_is_marked() {
+        let a = tree("dirdiff-eol-a", &[("f.txt", b"one\ntwo")]);
+        let b = tree("dirdiff-eol-b", &[("f.txt", b"one\nthree")]);
+        let patch = diff_dirs(&a, &b, &[]).unwrap();
+        assert!(patch.ends_with(
+            " one\n-two\n\\ No newline at end of file\n+three\n\\ No newline at end of file\n"
+        ));
+    }
+
+    #[test]
+    fn excluded_globs_and_vcs_dirs_are_skipped() {
+        let a = tree("dirdiff-skip-a", &[("keep.txt", b"a\n")]);
+        let b = tree(
+            "dirdiff-skip-b",
+            &[
+                ("keep.txt", b"b\n"),
+                ("target/debug/out", b"x\n"),
+                ("src/target/out", b"x\n"),
+                ("run.log", b"x\n"),
+                ("build/gen/x.c", b"x\n"),
+                ("docs/build/x.md", b"x\n"),
+                (".git/HEAD", b"ref\n"),
+                ("sub/.hg/store", b"x\n"),
+            ],
+        );
+        let exclude = ["target", "*.log", "build/**"].map(String::from);
+        let patch = diff_dirs(&a, &b, &exclude).unwrap();
+        let paths: Vec<&str> = patch
+            .lines()
+            .filter_map(|l| l.strip_prefix("diff --git a/"))
+            .collect();
+        assert_eq!(
+            paths,
+            ["docs/build/x.md b/docs/build/x.md", "keep.txt b/keep.txt"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn executable_bits_give_file_and_mode_changes() {
+        use std::os::unix::fs::PermissionsExt;
+        let a = tree(
+            "dirdiff-mode-a",
+            &[
+                ("run.sh", b"echo\n"),
+                ("tool.sh", b"a\n"),
+                ("old.sh", b"x\n"),
+            ],
+        );
+        let b = tree(
+            "dirdiff-mode-b",
+            &[
+                ("run.sh", b"echo\n"),
+                ("tool.sh", b"b\n"),
+                ("new.sh", b"y\n"),
+            ],
+        );
+        let exec =
+            |path: PathBuf| fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+        exec(b.join("run.sh"));
+        exec(b.join("tool.sh"));
+        exec(a.join("old.sh"));
+        exec(b.join("new.sh"));
+
+        let patch = diff_dirs(&a, &b, &[]).unwrap();
+        assert!(patch.contains("diff --git a/new.sh b/new.sh\nnew file mode 100755\n"));
+        assert!(patch.contains("diff --git a/old.sh b/old.sh\ndeleted file mode 100755\n"));
+        assert!(patch.contains(
+            "diff --git a/run.sh b/run.sh\nold mode 100644\nnew mode 100755\ndiff --git a/tool.sh"
+        ));
+        assert!(patch.contains(
+            "diff --git a/tool.sh b/tool.sh\nold mode 100644\nnew mode 100755\n--- a/tool.sh\n"
+        ));
+        // The mode-only change is a header-only entry of its own
+        let (files, hunks) = crate::parse_unified_diff(&patch).unwrap();
+        let run = files.iter().find(|f| f.path() == "run.sh").unwrap();
+        assert_eq!(run.headers.len(), 3);
+        let [h] = run.hunks[..] else {
+            panic!("{:?}", run.hunks)
+        };
+        assert_eq!(hunks[h].display, "(mode change)");
+    }
+}
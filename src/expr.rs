@@ -0,0 +1,635 @@
+//! Selection expressions: a tiny language for picking hunks, shared by `--expr` and the
+//! `:select-expr` command.
+//!
+//! ```text
+//! path(src/**) and grep(TODO) and not size>50
+//! (pure-add or ws-only) and dels<3
+//! ```
+//!
+//! Predicates: `path(GLOB)`, `group(N)` (the N-th file section of the input), `grep(REGEX)`
+//! (added/removed lines), `adds`/`dels`/`size` compared with `>`, `>=`, `<`, `<=` or `=`, and
+//! the flags `pure-add`, `pure-del`, `ws-only`. Combine with `and`/`&&`, `or`/`||`, `not`/`!`
+//! and parentheses; `not` binds tightest, then `and`, then `or`.
+//!
+//! Header-only entries (renames, mode changes, binaries, deletions) have no body, so the
+//! predicates about changed lines are neither true nor false for them; `not` leaves that
+//! unknown, `and`/`or` decide without it where they can, and an entry is selected only when
+//! the whole expression is true.
+
+use crate::{FileDiff, Hunk, change_counts};
+use regex::Regex;
+use std::fmt;
+
+/// A parse error pointing at the offending part of the expression.
+#[derive(Debug)]
+pub struct ExprError {
+    pub source: String,
+    pub pos: usize,
+    pub len: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let col = self.source[..self.pos].chars().count();
+        let width = self.source[self.pos..self.pos + self.len]
+            .chars()
+            .count()
+            .max(1);
+        writeln!(f, "{} (column {})", self.message, col + 1)?;
+        writeln!(f, "  {}", self.source)?;
+        write!(f, "  {}{}", " ".repeat(col), "^".repeat(width))
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+impl ExprError {
+    /// One-line form for the status bar.
+    pub fn short(&self) -> String {
+        let col = self.source[..self.pos].chars().count() + 1;
+        let token = &self.source[self.pos..self.pos + self.len];
+        format!("{} at column {col}: {token:?}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Cmp {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+}
+
+impl Cmp {
+    fn eval(self, lhs: usize, rhs: usize) -> bool {
+        match self {
+            Cmp::Gt => lhs > rhs,
+            Cmp::Ge => lhs >= rhs,
+            Cmp::Lt => lhs < rhs,
+            Cmp::Le => lhs <= rhs,
+            Cmp::Eq => lhs == rhs,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Metric {
+    Adds,
+    Dels,
+    Size,
+}
+
+/// A compiled expression.
+#[derive(Debug)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Path(String),
+    Group(usize),
+    Grep(Regex),
+    Count(Metric, Cmp, usize),
+    PureAdd,
+    PureDel,
+    WsOnly,
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Expr, ExprError> {
+        let tokens = tokenize(source)?;
+        let mut p = Parser {
+            source,
+            tokens,
+            pos: 0,
+        };
+        let expr = p.or()?;
+        if let Some(t) = p.tokens.get(p.pos) {
+            return Err(p.error_at(t, "unexpected token"));
+        }
+        Ok(expr)
+    }
+
+    pub fn matches(&self, file: &FileDiff, hunk: &Hunk) -> bool {
+        self.eval(file, hunk) == Some(true)
+    }
+
+    /// Three-valued evaluation: `None` when the answer depends on a hunk body the entry
+    /// doesn't have.
+    fn eval(&self, file: &FileDiff, hunk: &Hunk) -> Option<bool> {
+        match self {
+            Expr::And(a, b) => match (a.eval(file, hunk), b.eval(file, hunk)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            Expr::Or(a, b) => match (a.eval(file, hunk), b.eval(file, hunk)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+            Expr::Not(e) => e.eval(file, hunk).map(|v| !v),
+            Expr::Path(glob) => Some(glob_match(glob, &file.path())),
+            Expr::Group(n) => Some(hunk.file_idx + 1 == *n),
+            _ if hunk.header_only => None,
+            body => Some(body.matches_body(hunk)),
+        }
+    }
+
+    fn matches_body(&self, hunk: &Hunk) -> bool {
+        match self {
+            Expr::Grep(re) => hunk
+                .lines
+                .iter()
+                .filter_map(|l| l.strip_prefix('+').or_else(|| l.strip_prefix('-')))
+                .any(|l| re.is_match(l)),
+            Expr::Count(metric, cmp, n) => {
+                let (adds, dels) = change_counts(&hunk.lines);
+                let value = match metric {
+                    Metric::Adds => adds,
+                    Metric::Dels => dels,
+                    Metric::Size => hunk.lines.len(),
+                };
+                cmp.eval(value, *n)
+            }
+            Expr::PureAdd => matches!(change_counts(&hunk.lines), (1.., 0)),
+            Expr::PureDel => matches!(change_counts(&hunk.lines), (0, 1..)),
+            Expr::WsOnly => whitespace_only(&hunk.lines),
+            _ => unreachable!("not a body predicate"),
+        }
+    }
+}
+
+/// True when the hunk changes something and the removed and added text are identical once all
+/// whitespace is ignored.
+fn whitespace_only(lines: &[String]) -> bool {
+    let squash = |prefix: char| -> String {
+        lines
+            .iter()
+            .filter_map(|l| l.strip_prefix(prefix))
+            .flat_map(|l| l.chars().filter(|c| !c.is_whitespace()))
+            .collect()
+    };
+    let (adds, dels) = change_counts(lines);
+    adds + dels > 0 && squash('-') == squash('+')
+}
+
+/// Glob match over `/`-separated paths: `*` stays within a component, `**` crosses them, `?`
+/// is one character. A pattern without `/` matches the file name in any directory.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    fn go(p: &[char], s: &[char]) -> bool {
+        match p {
+            [] => s.is_empty(),
+            ['*', '*', '/', rest @ ..] => {
+                (0..=s.len()).any(|i| (i == 0 || s[i - 1] == '/') && go(rest, &s[i..]))
+            }
+            ['*', '*', rest @ ..] => (0..=s.len()).any(|i| go(rest, &s[i..])),
+            ['*', rest @ ..] => (0..=s.len())
+                .take_while(|&i| i == 0 || s[i - 1] != '/')
+                .any(|i| go(rest, &s[i..])),
+            ['?', rest @ ..] => !s.is_empty() && s[0] != '/' && go(rest, &s[1..]),
+            [c, rest @ ..] => s.first() == Some(c) && go(rest, &s[1..]),
+        }
+    }
+    let p: Vec<char> = pattern.chars().collect();
+    if !pattern.contains('/') {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        return go(&p, &name.chars().collect::<Vec<_>>());
+    }
+    go(&p, &path.chars().collect::<Vec<_>>())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Word(String),
+    /// Parenthesized argument of a function-style predicate, taken verbatim.
+    Arg(String),
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Cmp(&'static str),
+    Number(usize),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    pos: usize,
+    len: usize,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, ExprError> {
+    let err = |pos: usize, len: usize, message: &str| ExprError {
+        source: source.to_string(),
+        pos,
+        len,
+        message: message.to_string(),
+    };
+    let bytes = source.as_bytes();
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let start = i;
+        let push = |tokens: &mut Vec<Token>, tok: Tok, end: usize| {
+            tokens.push(Token {
+                tok,
+                pos: start,
+                len: end - start,
+            })
+        };
+        match c {
+            b' ' | b'\t' => {
+                i += 1;
+            }
+            b'(' if matches!(
+                tokens.last(),
+                Some(Token {
+                    tok: Tok::Word(_),
+                    ..
+                })
+            ) =>
+            {
+                // Function argument: everything up to the matching ')', quotes optional
+                let mut depth = 1;
+                let mut j = i + 1;
+                while j < bytes.len() {
+                    match bytes[j] {
+                        b'(' => depth += 1,
+                        b')' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                    j += 1;
+                }
+                if j >= bytes.len() {
+                    return Err(err(i, bytes.len() - i, "unclosed '('"));
+                }
+                let raw = source[i + 1..j].trim();
+                let unquoted = raw
+                    .strip_prefix('"')
+                    .and_then(|r| r.strip_suffix('"'))
+                    .or_else(|| raw.strip_prefix('\'').and_then(|r| r.strip_suffix('\'')))
+                    .unwrap_or(raw);
+                i = j + 1;
+                push(&mut tokens, Tok::Arg(unquoted.to_string()), i);
+            }
+            b'(' => {
+                i += 1;
+                push(&mut tokens, Tok::Open, i);
+            }
+            b')' => {
+                i += 1;
+                push(&mut tokens, Tok::Close, i);
+            }
+            b'!' if bytes.get(i + 1) != Some(&b'=') => {
+                i += 1;
+                push(&mut tokens, Tok::Not, i);
+            }
+            b'&' | b'|' => {
+                if bytes.get(i + 1) != Some(&c) {
+                    return Err(err(i, 1, "expected '&&' or '||'"));
+                }
+                i += 2;
+                let tok = if c == b'&' { Tok::And } else { Tok::Or };
+                push(&mut tokens, tok, i);
+            }
+            b'>' | b'<' | b'=' => {
+                let op = match (c, bytes.get(i + 1)) {
+                    (b'>', Some(b'=')) => ">=",
+                    (b'<', Some(b'=')) => "<=",
+                    (b'=', Some(b'=')) => "==",
+                    (b'>', _) => ">",
+                    (b'<', _) => "<",
+                    _ => "=",
+                };
+                i += op.len();
+                push(&mut tokens, Tok::Cmp(op), i);
+            }
+            b'0'..=b'9' => {
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let n = source[start..i]
+                    .parse()
+                    .map_err(|_| err(start, i - start, "number too large"))?;
+                push(&mut tokens, Tok::Number(n), i);
+            }
+            c if c.is_ascii_alphabetic() => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'-') {
+                    i += 1;
+                }
+                let tok = match &source[start..i] {
+                    "and" => Tok::And,
+                    "or" => Tok::Or,
+                    "not" => Tok::Not,
+                    w => Tok::Word(w.to_string()),
+                };
+                push(&mut tokens, tok, i);
+            }
+            _ => {
+                let len = source[i..].chars().next().map_or(1, char::len_utf8);
+                return Err(err(i, len, "unexpected character"));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn error_at(&self, t: &Token, message: &str) -> ExprError {
+        ExprError {
+            source: self.source.to_string(),
+            pos: t.pos,
+            len: t.len,
+            message: message.to_string(),
+        }
+    }
+
+    fn error_at_end(&self, message: &str) -> ExprError {
+        ExprError {
+            source: self.source.to_string(),
+            pos: self.source.len(),
+            len: 0,
+            message: message.to_string(),
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let t = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        t
+    }
+
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos).map(|t| &t.tok)
+    }
+
+    fn or(&mut self) -> Result<Expr, ExprError> {
+        let mut lhs = self.and()?;
+        while self.peek() == Some(&Tok::Or) {
+            self.pos += 1;
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr, ExprError> {
+        let mut lhs = self.not()?;
+        while self.peek() == Some(&Tok::And) {
+            self.pos += 1;
+            lhs = Expr::And(Box::new(lhs), Box::new(self.not()?));
+        }
+        Ok(lhs)
+    }
+
+    fn not(&mut self) -> Result<Expr, ExprError> {
+        if self.peek() == Some(&Tok::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, ExprError> {
+        let Some(t) = self.next() else {
+            return Err(self.error_at_end("expected a predicate"));
+        };
+        match &t.tok {
+            Tok::Open => {
+                let inner = self.or()?;
+                match self.next() {
+                    Some(Token {
+                        tok: Tok::Close, ..
+                    }) => Ok(inner),
+                    Some(other) => Err(self.error_at(&other, "expected ')'")),
+                    None => Err(self.error_at(&t, "unclosed '('")),
+                }
+            }
+            Tok::Word(w) => self.predicate(&t, w),
+            _ => Err(self.error_at(&t, "expected a predicate")),
+        }
+    }
+
+    fn predicate(&mut self, t: &Token, word: &str) -> Result<Expr, ExprError> {
+        match word {
+            "path" | "grep" | "group" => {
+                let (arg, pos, len) = match self.next() {
+                    Some(Token {
+                        tok: Tok::Arg(a),
+                        pos,
+                        len,
+                    }) => (a, pos, len),
+                    _ => return Err(self.error_at(t, &format!("{word}() needs an argument"))),
+                };
+                // Bad arguments are reported at the argument, not the predicate's name
+                let bad_arg = |message: &str| ExprError {
+                    source: self.source.to_string(),
+                    pos,
+                    len,
+                    message: message.to_string(),
+                };
+                match word {
+                    "path" => Ok(Expr::Path(arg)),
+                    "group" => match arg.parse() {
+                        Ok(n) if n > 0 => Ok(Expr::Group(n)),
+                        _ => Err(bad_arg("expected a file number, counting from 1")),
+                    },
+                    _ => Regex::new(&arg)
+                        .map(Expr::Grep)
+                        .map_err(|_| bad_arg("invalid regular expression")),
+                }
+            }
+            "adds" | "dels" | "size" => {
+                let metric = match word {
+                    "adds" => Metric::Adds,
+                    "dels" => Metric::Dels,
+                    _ => Metric::Size,
+                };
+                let cmp = match self.next() {
+                    Some(Token {
+                        tok: Tok::Cmp(op), ..
+                    }) => match op {
+                        ">" => Cmp::Gt,
+                        ">=" => Cmp::Ge,
+                        "<" => Cmp::Lt,
+                        "<=" => Cmp::Le,
+                        _ => Cmp::Eq,
+                    },
+                    Some(other) => return Err(self.error_at(&other, "expected a comparison")),
+                    None => return Err(self.error_at_end("expected a comparison")),
+                };
+                match self.next() {
+                    Some(Token {
+                        tok: Tok::Number(n),
+                        ..
+                    }) => Ok(Expr::Count(metric, cmp, n)),
+                    Some(other) => Err(self.error_at(&other, "expected a number")),
+                    None => Err(self.error_at_end("expected a number")),
+                }
+            }
+            "pure-add" => Ok(Expr::PureAdd),
+            "pure-del" => Ok(Expr::PureDel),
+            "ws-only" => Ok(Expr::WsOnly),
+            _ => Err(self.error_at(t, "unknown predicate")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_unified_diff;
+
+    const PATCH: &str = "\
+diff --git a/src/main.rs b/src/main.rs
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -1,2 +1,3 @@
+ fn main() {
++    // TODO: greet
+ }
+@@ -10,3 +11,3 @@
+ fn helper() {
+-    let x=1;
++    let x = 1;
+ }
+diff --git a/docs/notes.md b/docs/notes.md
+--- a/docs/notes.md
++++ b/docs/notes.md
+@@ -1,3 +1,1 @@
+-old
+-TODO later
+ kept
+diff --git a/src/gone.rs b/src/gone.rs
+deleted file mode 100644
+index 3b18e51..0000000
+";
+
+    /// Indices of the hunks `expr` selects: 0 pure add, 1 whitespace fix, 2 pure delete,
+    /// 3 header-only deletion.
+    fn selected(expr: &str) -> Vec<usize> {
+        let (files, hunks) = parse_unified_diff(PATCH).unwrap();
+        let expr = Expr::parse(expr).unwrap_or_else(|e| panic!("{e}"));
+        (0..hunks.len())
+            .filter(|&i| expr.matches(&files[hunks[i].file_idx], &hunks[i]))
+            .collect()
+    }
+
+    /// (column, token) an expression fails to parse at.
+    fn error(expr: &str) -> (usize, String) {
+        let e = Expr::parse(expr).unwrap_err();
+        (e.pos, e.source[e.pos..e.pos + e.len].to_string())
+    }
+
+    #[test]
+    fn predicates() {
+        assert_eq!(selected("path(src/**)"), [0, 1, 3]);
+        assert_eq!(selected("path(*.md)"), [2]);
+        assert_eq!(selected("path('src/*.rs')"), [0, 1, 3]);
+        assert_eq!(selected("group(1)"), [0, 1]);
+        assert_eq!(selected("group(3)"), [3]);
+        assert_eq!(selected("grep(TODO)"), [0, 2]);
+        assert_eq!(selected(r#"grep("^TODO")"#), [2]);
+        // Context lines don't count
+        assert_eq!(selected("grep(helper)"), Vec::<usize>::new());
+        assert_eq!(selected("adds>0"), [0, 1]);
+        assert_eq!(selected("adds=1"), [0, 1]);
+        assert_eq!(selected("adds==1"), [0, 1]);
+        assert_eq!(selected("dels>=2"), [2]);
+        assert_eq!(selected("dels<1"), [0]);
+        assert_eq!(selected("size<=3"), [0, 2]);
+        assert_eq!(selected("pure-add"), [0]);
+        assert_eq!(selected("pure-del"), [2]);
+        assert_eq!(selected("ws-only"), [1]);
+    }
+
+    #[test]
+    fn precedence_and_grouping() {
+        // not > and > or
+        assert_eq!(selected("pure-add or pure-del and grep(later)"), [0, 2]);
+        assert_eq!(selected("(pure-add or pure-del) and grep(later)"), [2]);
+        assert_eq!(selected("not pure-add and path(src/**)"), [1]);
+        assert_eq!(selected("not (pure-add and path(src/**))"), [1, 2]);
+        assert_eq!(selected("!pure-add && !pure-del || ws-only"), [1]);
+        assert_eq!(selected("not not pure-del"), [2]);
+    }
+
+    #[test]
+    fn header_only_entries_match_on_path_alone() {
+        // Nothing is known about their changed lines, either way
+        assert_eq!(selected("size<1000"), [0, 1, 2]);
+        assert_eq!(selected("not size<1000"), Vec::<usize>::new());
+        assert_eq!(selected("not grep(TODO)"), [1]);
+        // What is known decides
+        assert_eq!(selected("path(src/gone.rs)"), [3]);
+        assert_eq!(selected("not path(src/main.rs)"), [2, 3]);
+        assert_eq!(selected("path(src/gone.rs) or grep(x)"), [1, 3]);
+        assert_eq!(
+            selected("path(src/gone.rs) and grep(x)"),
+            Vec::<usize>::new()
+        );
+        assert_eq!(selected("path(docs/**) and pure-add"), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn errors_point_at_the_offending_token() {
+        assert_eq!(error("pure-add and nope"), (13, "nope".into()));
+        assert_eq!(error("pure-add & ws-only"), (9, "&".into()));
+        assert_eq!(error("pure-add ws-only"), (9, "ws-only".into()));
+        assert_eq!(error("pure-add #"), (9, "#".into()));
+        assert_eq!(error("grep(a[) and pure-add"), (4, "(a[)".into()));
+        assert_eq!(error("group(0)"), (5, "(0)".into()));
+        assert_eq!(error("group(x)"), (5, "(x)".into()));
+        assert_eq!(error("path and ws-only"), (0, "path".into()));
+        assert_eq!(error("size 3"), (5, "3".into()));
+        assert_eq!(error("size > big"), (7, "big".into()));
+        assert_eq!(
+            error("(pure-add or ws-only ws-only"),
+            (21, "ws-only".into())
+        );
+        assert_eq!(error("(pure-add"), (0, "(".into()));
+        assert_eq!(error("path(src"), (4, "(src".into()));
+        assert_eq!(error("pure-add and"), (12, "".into()));
+        assert_eq!(error("size>"), (5, "".into()));
+        assert_eq!(error(") pure-add"), (0, ")".into()));
+        assert_eq!(
+            error("adds>99999999999999999999999"),
+            (5, "99999999999999999999999".into())
+        );
+    }
+
+    #[test]
+    fn error_display_underlines_the_token() {
+        let e = Expr::parse("pure-add and nope").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "unknown predicate (column 14)\n  pure-add and nope\n               ^^^^"
+        );
+        assert_eq!(e.short(), "unknown predicate at column 14: \"nope\"");
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("src/**", "src/a/b.rs"));
+        assert!(glob_match("src/**/b.rs", "src/b.rs"));
+        assert!(glob_match("src/*.rs", "src/a.rs"));
+        assert!(!glob_match("src/*.rs", "src/a/b.rs"));
+        assert!(glob_match("*.rs", "deep/down/x.rs"));
+        assert!(glob_match("?.rs", "x.rs"));
+        assert!(!glob_match("?.rs", "xy.rs"));
+    }
+}
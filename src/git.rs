@@ -0,0 +1,137 @@
+//! Thin wrappers around the `git` command line.
+
+use anyhow::{Context, Result, anyhow};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Run `git apply -v` on `patch` inside `root`, against the index instead of the working tree
+/// when `cached` is set. Returns git's verbose report on success, and git's error lines as the
+/// error otherwise.
+pub fn apply(root: &Path, patch: &str, cached: bool) -> Result<String> {
+    let mut cmd = Command::new("git");
+    cmd.arg("-C").arg(root).args(["apply", "-v"]);
+    if cached {
+        cmd.arg("--cached");
+    }
+    let mut child = cmd
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("running git apply")?;
+    child
+        .stdin
+        .take()
+        .expect("piped stdin")
+        .write_all(patch.as_bytes())
+        .context("feeding the patch to git apply")?;
+    let out = child.wait_with_output().context("running git apply")?;
+    let stderr = String::from_utf8_lossy(&out.stderr).into_owned();
+    if out.status.success() {
+        return Ok(stderr);
+    }
+    let errors: Vec<&str> = stderr
+        .lines()
+        .filter_map(|l| l.strip_prefix("error: "))
+        .filter(|l| !l.starts_with("while searching for"))
+        .collect();
+    Err(anyhow!(
+        "git apply failed: {}",
+        if errors.is_empty() {
+            stderr.trim().to_string()
+        } else {
+            errors.join("; ")
+        }
+    ))
+}
+
+/// Hunks that `git apply -v` reported applying away from where their header put them, from its
+/// `Hunk #N succeeded at L (offset K lines).` lines: the hunk number (from 1, counted within its
+/// file) and the shift in lines.
+pub fn hunk_offsets(verbose: &str) -> Vec<(usize, isize)> {
+    verbose
+        .lines()
+        .filter_map(|l| {
+            let (n, rest) = l.strip_prefix("Hunk #")?.split_once(' ')?;
+            let (_, offset) = rest.split_once("(offset ")?;
+            let offset = offset.split_whitespace().next()?;
+            Some((n.parse().ok()?, offset.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Contents of the blob `id` (abbreviated ids work too) in the repository at `root`.
+pub fn cat_blob(root: &Path, id: &str) -> Result<String> {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["cat-file", "blob", id])
+        .stderr(Stdio::null())
+        .output()
+        .context("running git cat-file")?;
+    if !out.status.success() {
+        return Err(anyhow!("blob {id} not found in {}", root.display()));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// The changes recorded in `stash@{n}` of the repository at `root`, as a patch.
+pub fn stash_patch(root: &Path, n: usize) -> Result<String> {
+    let stash = format!("stash@{{{n}}}");
+    let exists = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["rev-parse", "--quiet", "--verify", &stash])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .context("running git rev-parse")?
+        .success();
+    if !exists {
+        return Err(anyhow!(
+            "no {stash} in {} (see `git stash list`)",
+            root.display()
+        ));
+    }
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(["stash", "show", "-p", &stash])
+        .output()
+        .context("running git stash show")?;
+    if !out.status.success() {
+        return Err(anyhow!(
+            "git stash show -p {stash} failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hunk_offsets_reads_git_apply_verbose_output() {
+        // As printed by `git apply -v`, including a hunk that only fit with less context
+        let verbose = "\
+Checking patch f...
+Hunk #1 succeeded at 3 (offset 1 line).
+Hunk #2 succeeded at 21 (offset 5 lines).
+Context reduced to (2/2) to apply fragment at 21
+Applied patch f cleanly.
+";
+        assert_eq!(hunk_offsets(verbose), [(1, 1), (2, 5)]);
+        let verbose = "\
+Checking patch f...
+Hunk #1 succeeded at 1 (offset -1 lines).
+Hunk #3 succeeded at 40 (offset -12 lines).
+Applied patch f cleanly.
+";
+        assert_eq!(hunk_offsets(verbose), [(1, -1), (3, -12)]);
+        assert!(hunk_offsets("Checking patch f...\nApplied patch f cleanly.\n").is_empty());
+    }
+}
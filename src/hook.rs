@@ -0,0 +1,93 @@
+//! `--post-save-cmd`: a shell command run after every successful write of the output, for
+//! integrations such as auto-applying or notifying.
+//!
+//! The command runs through `sh -c` with its output discarded (it would garble the TUI) and
+//! gets these environment variables:
+//!
+//! * `PATCHERS_OUTPUT`: the output patch just written
+//! * `PATCHERS_INPUT`: the input patch
+//! * `PATCHERS_SELECTED`: how many hunks the output holds
+
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+
+pub struct PostSave {
+    cmd: String,
+    input: PathBuf,
+    running: Option<Child>,
+    // Saves made while the command was still running: it runs once more when it's done
+    rerun: Option<(PathBuf, usize)>,
+}
+
+impl PostSave {
+    pub fn new(cmd: String, input: PathBuf) -> Self {
+        Self {
+            cmd,
+            input,
+            running: None,
+            rerun: None,
+        }
+    }
+
+    fn command(&self, output: &Path, selected: usize) -> Command {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c")
+            .arg(&self.cmd)
+            .env("PATCHERS_OUTPUT", output)
+            .env("PATCHERS_INPUT", &self.input)
+            .env("PATCHERS_SELECTED", selected.to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        cmd
+    }
+
+    /// Start the command in the background, or queue a rerun if it's still busy.
+    pub fn start(&mut self, output: &Path, selected: usize) -> Result<()> {
+        if self.running.is_some() {
+            self.rerun = Some((output.to_path_buf(), selected));
+            return Ok(());
+        }
+        let child = self
+            .command(output, selected)
+            .spawn()
+            .context("starting --post-save-cmd")?;
+        self.running = Some(child);
+        Ok(())
+    }
+
+    /// Check on the running command without blocking. Returns a message when it failed.
+    pub fn poll(&mut self) -> Result<Option<String>> {
+        let Some(child) = &mut self.running else {
+            return Ok(None);
+        };
+        let Some(status) = child.try_wait().context("waiting for --post-save-cmd")? else {
+            return Ok(None);
+        };
+        self.running = None;
+        if let Some((output, selected)) = self.rerun.take() {
+            self.start(&output, selected)?;
+        }
+        Ok(failure(status))
+    }
+
+    /// Run the command to completion (for `--batch`).
+    pub fn run(&self, output: &Path, selected: usize) -> Result<Option<String>> {
+        let status = self
+            .command(output, selected)
+            .status()
+            .context("running --post-save-cmd")?;
+        Ok(failure(status))
+    }
+}
+
+fn failure(status: ExitStatus) -> Option<String> {
+    if status.success() {
+        return None;
+    }
+    Some(match status.code() {
+        Some(code) => format!("post-save command exited with status {code}"),
+        None => "post-save command was killed by a signal".to_string(),
+    })
+}
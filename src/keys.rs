@@ -0,0 +1,650 @@
+//! Key binding registry: every key the TUI reacts to is declared here, per mode, so bindings
+//! can be listed, overridden from the command line and checked for conflicts at startup.
+
+use anyhow::{Result, anyhow, bail};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::fmt;
+
+/// Input modes; each has its own set of bindings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Normal,
+    Line,
+    /// A save held back unreliable hunks and asks whether to write them.
+    Confirm,
+}
+
+impl Mode {
+    pub const ALL: [Mode; 3] = [Mode::Normal, Mode::Line, Mode::Confirm];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Normal => "normal",
+            Mode::Line => "line",
+            Mode::Confirm => "confirm",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Mode> {
+        Mode::ALL.into_iter().find(|m| m.name() == s)
+    }
+}
+
+/// Everything a key can trigger.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Help,
+    CommandLine,
+    FilePicker,
+    MoveUp,
+    MoveDown,
+    MoveTop,
+    MoveBottom,
+    ToggleHunk,
+    MarkRestOfFile,
+    ClearFile,
+    ToggleFileView,
+    ApplyHunk,
+    WholeFile,
+    ShowHeaders,
+    BlockView,
+    ChangesOnly,
+    FullLines,
+    NextChange,
+    NextAddsHunk,
+    NextDelsHunk,
+    CopyMarkdown,
+    CopyLocation,
+    ToggleRenderBudget,
+    LineMode,
+    LineUp,
+    LineDown,
+    ToggleLine,
+    LeaveLineMode,
+    ConfirmUnreliable,
+}
+
+impl Action {
+    const ALL: [Action; 30] = [
+        Action::Quit,
+        Action::Help,
+        Action::CommandLine,
+        Action::FilePicker,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveTop,
+        Action::MoveBottom,
+        Action::ToggleHunk,
+        Action::MarkRestOfFile,
+        Action::ClearFile,
+        Action::ToggleFileView,
+        Action::ApplyHunk,
+        Action::WholeFile,
+        Action::ShowHeaders,
+        Action::BlockView,
+        Action::ChangesOnly,
+        Action::FullLines,
+        Action::NextChange,
+        Action::NextAddsHunk,
+        Action::NextDelsHunk,
+        Action::CopyMarkdown,
+        Action::CopyLocation,
+        Action::ToggleRenderBudget,
+        Action::LineMode,
+        Action::LineUp,
+        Action::LineDown,
+        Action::ToggleLine,
+        Action::LeaveLineMode,
+        Action::ConfirmUnreliable,
+    ];
+
+    /// Name used by `--bind`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Help => "help",
+            Action::CommandLine => "command",
+            Action::FilePicker => "file-picker",
+            Action::MoveUp => "up",
+            Action::MoveDown => "down",
+            Action::MoveTop => "top",
+            Action::MoveBottom => "bottom",
+            Action::ToggleHunk => "toggle",
+            Action::MarkRestOfFile => "mark-rest",
+            Action::ClearFile => "clear-file",
+            Action::ToggleFileView => "file-view",
+            Action::ApplyHunk => "apply",
+            Action::WholeFile => "whole-file",
+            Action::ShowHeaders => "headers",
+            Action::BlockView => "block-view",
+            Action::ChangesOnly => "changes-only",
+            Action::FullLines => "full-lines",
+            Action::NextChange => "next-change",
+            Action::NextAddsHunk => "next-adds",
+            Action::NextDelsHunk => "next-dels",
+            Action::CopyMarkdown => "copy-markdown",
+            Action::CopyLocation => "copy-location",
+            Action::ToggleRenderBudget => "render-budget",
+            Action::LineMode => "line-mode",
+            Action::LineUp => "line-up",
+            Action::LineDown => "line-down",
+            Action::ToggleLine => "toggle-line",
+            Action::LeaveLineMode => "leave-line-mode",
+            Action::ConfirmUnreliable => "confirm-unreliable",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Help => "show this help",
+            Action::CommandLine => "command line (:select-expr EXPR)",
+            Action::FilePicker => "pick a file by fuzzy name and jump to its first hunk",
+            Action::MoveUp => "move up",
+            Action::MoveDown => "move down",
+            Action::MoveTop => "go to first hunk",
+            Action::MoveBottom => "go to last hunk",
+            Action::ToggleHunk => "toggle hunk (or file) & save",
+            Action::MarkRestOfFile => "mark this and the file's later hunks & save",
+            Action::ClearFile => "unmark all hunks of the current file & save",
+            Action::ToggleFileView => "list files only / list hunks",
+            Action::ApplyHunk => "git apply the hunk (or file) to the repository",
+            Action::WholeFile => "show the hunk inside its whole original file / hunk only",
+            Action::ShowHeaders => "show / hide the file's raw headers in the preview",
+            Action::BlockView => "preview removed lines next to their replacements / flat diff",
+            Action::ChangesOnly => {
+                "preview only the changed lines, one ⋯ between blocks / with context"
+            }
+            Action::FullLines => "show this hunk's long lines in full / cut them again",
+            Action::NextChange => "scroll the preview to the next block of changes",
+            Action::NextAddsHunk => "jump to the next hunk made mostly of additions",
+            Action::NextDelsHunk => "jump to the next hunk made mostly of deletions",
+            Action::CopyMarkdown => "copy hunk as a markdown diff block",
+            Action::CopyLocation => "copy the hunk's path and line for an editor (--jump-format)",
+            Action::ToggleRenderBudget => "full rendering / adapt to slow terminals",
+            Action::LineMode => "pick individual lines",
+            Action::LineUp => "previous change line",
+            Action::LineDown => "next change line",
+            Action::ToggleLine => "include/exclude line & save",
+            Action::LeaveLineMode => "back to hunks",
+            Action::ConfirmUnreliable => "write the unreliable hunks the last save held back",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Action> {
+        Action::ALL.into_iter().find(|a| a.name() == s)
+    }
+}
+
+/// A single key press: code plus modifiers (Shift is folded into the character).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Chord {
+    code: KeyCode,
+    mods: KeyModifiers,
+}
+
+impl Chord {
+    pub fn code(self) -> KeyCode {
+        self.code
+    }
+
+    pub fn from_event(key: KeyEvent) -> Self {
+        let mut mods = key.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT);
+        if !matches!(key.code, KeyCode::Char(_)) {
+            mods |= key.modifiers & KeyModifiers::SHIFT;
+        }
+        Chord {
+            code: key.code,
+            mods,
+        }
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.code {
+            KeyCode::Char(' ') => "Space".to_string(),
+            KeyCode::Char('<') => "lt".to_string(),
+            KeyCode::Char(c) => c.to_string(),
+            KeyCode::Enter => "Enter".into(),
+            KeyCode::Esc => "Esc".into(),
+            KeyCode::Tab => "Tab".into(),
+            KeyCode::BackTab => "S-Tab".into(),
+            KeyCode::Backspace => "BS".into(),
+            KeyCode::Up => "Up".into(),
+            KeyCode::Down => "Down".into(),
+            KeyCode::Left => "Left".into(),
+            KeyCode::Right => "Right".into(),
+            KeyCode::Home => "Home".into(),
+            KeyCode::End => "End".into(),
+            KeyCode::PageUp => "PageUp".into(),
+            KeyCode::PageDown => "PageDown".into(),
+            KeyCode::F(n) => format!("F{n}"),
+            other => format!("{other:?}"),
+        };
+        let mut prefix = String::new();
+        if self.mods.contains(KeyModifiers::CONTROL) {
+            prefix.push_str("C-");
+        }
+        if self.mods.contains(KeyModifiers::ALT) {
+            prefix.push_str("M-");
+        }
+        if self.mods.contains(KeyModifiers::SHIFT) {
+            prefix.push_str("S-");
+        }
+        let plain_char = matches!(self.code, KeyCode::Char(c) if c != ' ' && c != '<');
+        if prefix.is_empty() && plain_char {
+            write!(f, "{name}")
+        } else {
+            write!(f, "<{prefix}{name}>")
+        }
+    }
+}
+
+/// Parse vim-style key notation: plain characters, or `<...>` groups such as `<Space>`,
+/// `<C-d>`, `<M-a>`, `<Down>`. `gg` is a sequence of two chords.
+pub fn parse_keys(spec: &str) -> Result<Vec<Chord>> {
+    let mut out = Vec::new();
+    let mut chars = spec.chars();
+    while let Some(c) = chars.next() {
+        if c != '<' {
+            out.push(Chord {
+                code: KeyCode::Char(c),
+                mods: KeyModifiers::NONE,
+            });
+            continue;
+        }
+        let mut group = String::new();
+        loop {
+            match chars.next() {
+                Some('>') => break,
+                Some(c) => group.push(c),
+                None => bail!("unclosed <{group} in {spec:?} (use <lt> for a literal <)"),
+            }
+        }
+        out.push(parse_group(&group).ok_or_else(|| anyhow!("unknown key <{group}> in {spec:?}"))?);
+    }
+    if out.is_empty() {
+        bail!("empty key sequence");
+    }
+    Ok(out)
+}
+
+fn parse_group(group: &str) -> Option<Chord> {
+    let mut mods = KeyModifiers::NONE;
+    let mut rest = group;
+    loop {
+        if let Some(r) = rest.strip_prefix("C-") {
+            mods |= KeyModifiers::CONTROL;
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix("M-").or_else(|| rest.strip_prefix("A-")) {
+            mods |= KeyModifiers::ALT;
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix("S-") {
+            mods |= KeyModifiers::SHIFT;
+            rest = r;
+        } else {
+            break;
+        }
+    }
+    let code = match rest {
+        "Space" => KeyCode::Char(' '),
+        "lt" => KeyCode::Char('<'),
+        "Enter" | "CR" => KeyCode::Enter,
+        "Esc" => KeyCode::Esc,
+        "Tab" if mods.contains(KeyModifiers::SHIFT) => {
+            mods -= KeyModifiers::SHIFT;
+            KeyCode::BackTab
+        }
+        "Tab" => KeyCode::Tab,
+        "BS" => KeyCode::Backspace,
+        "Up" => KeyCode::Up,
+        "Down" => KeyCode::Down,
+        "Left" => KeyCode::Left,
+        "Right" => KeyCode::Right,
+        "Home" => KeyCode::Home,
+        "End" => KeyCode::End,
+        "PageUp" => KeyCode::PageUp,
+        "PageDown" => KeyCode::PageDown,
+        f if f.len() > 1 && f.starts_with('F') => KeyCode::F(f[1..].parse().ok()?),
+        c if c.chars().count() == 1 => KeyCode::Char(c.chars().next()?),
+        _ => return None,
+    };
+    if matches!(code, KeyCode::Char(_)) {
+        mods -= KeyModifiers::SHIFT;
+    }
+    Some(Chord { code, mods })
+}
+
+pub fn format_keys(keys: &[Chord]) -> String {
+    keys.iter().map(|c| c.to_string()).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    User,
+}
+
+#[derive(Debug, Clone)]
+pub struct Binding {
+    pub mode: Mode,
+    pub keys: Vec<Chord>,
+    pub action: Action,
+    pub source: Source,
+    /// Set when a later binding for the same keys takes precedence.
+    pub overridden: bool,
+}
+
+/// Outcome of feeding the pending key sequence to the keymap.
+pub enum Resolved {
+    Action(Action),
+    /// The keys so far are a prefix of a longer binding; wait for more.
+    Pending,
+    Unbound,
+}
+
+pub struct Keymap {
+    bindings: Vec<Binding>,
+}
+
+const DEFAULTS: &[(Mode, &str, Action)] = &[
+    (Mode::Normal, "q", Action::Quit),
+    (Mode::Normal, "?", Action::Help),
+    (Mode::Normal, ":", Action::CommandLine),
+    (Mode::Normal, "k", Action::MoveUp),
+    (Mode::Normal, "<Up>", Action::MoveUp),
+    (Mode::Normal, "j", Action::MoveDown),
+    (Mode::Normal, "<Down>", Action::MoveDown),
+    (Mode::Normal, "gg", Action::MoveTop),
+    (Mode::Normal, "<Home>", Action::MoveTop),
+    (Mode::Normal, "G", Action::MoveBottom),
+    (Mode::Normal, "<End>", Action::MoveBottom),
+    (Mode::Normal, "<Space>", Action::ToggleHunk),
+    (Mode::Normal, "<Enter>", Action::ToggleHunk),
+    (Mode::Normal, "M", Action::MarkRestOfFile),
+    (Mode::Normal, "X", Action::ClearFile),
+    (Mode::Normal, "f", Action::ToggleFileView),
+    (Mode::Normal, "F", Action::FilePicker),
+    (Mode::Normal, "v", Action::LineMode),
+    (Mode::Normal, "y", Action::CopyMarkdown),
+    (Mode::Normal, "Y", Action::CopyLocation),
+    (Mode::Normal, "a", Action::ApplyHunk),
+    (Mode::Normal, "w", Action::WholeFile),
+    (Mode::Normal, "H", Action::ShowHeaders),
+    (Mode::Normal, "b", Action::BlockView),
+    (Mode::Normal, "c", Action::ChangesOnly),
+    (Mode::Normal, "L", Action::FullLines),
+    (Mode::Normal, "}", Action::NextChange),
+    (Mode::Normal, "<M-a>", Action::NextAddsHunk),
+    (Mode::Normal, "<M-d>", Action::NextDelsHunk),
+    (Mode::Normal, "R", Action::ToggleRenderBudget),
+    (Mode::Line, "q", Action::Quit),
+    (Mode::Line, "?", Action::Help),
+    (Mode::Line, "k", Action::LineUp),
+    (Mode::Line, "<Up>", Action::LineUp),
+    (Mode::Line, "j", Action::LineDown),
+    (Mode::Line, "<Down>", Action::LineDown),
+    (Mode::Line, "<Space>", Action::ToggleLine),
+    (Mode::Line, "<Enter>", Action::ToggleLine),
+    (Mode::Line, "v", Action::LeaveLineMode),
+    (Mode::Line, "<Esc>", Action::LeaveLineMode),
+    (Mode::Confirm, "y", Action::ConfirmUnreliable),
+];
+
+impl Keymap {
+    /// Built-in bindings followed by user overrides given as `MODE:KEYS=ACTION` (the mode
+    /// defaults to `normal`). Later bindings win over earlier ones for the same keys.
+    pub fn new(user: &[String]) -> Result<Self> {
+        let mut bindings: Vec<Binding> = DEFAULTS
+            .iter()
+            .map(|&(mode, keys, action)| Binding {
+                mode,
+                keys: parse_keys(keys).expect("valid default binding"),
+                action,
+                source: Source::Default,
+                overridden: false,
+            })
+            .collect();
+        for spec in user {
+            bindings.push(parse_binding(spec).map_err(|e| anyhow!("--bind {spec:?}: {e}"))?);
+        }
+        for i in 0..bindings.len() {
+            let later = bindings[i + 1..]
+                .iter()
+                .any(|b| b.mode == bindings[i].mode && b.keys == bindings[i].keys);
+            bindings[i].overridden = later;
+        }
+        Ok(Self { bindings })
+    }
+
+    pub fn bindings(&self) -> &[Binding] {
+        &self.bindings
+    }
+
+    pub fn resolve(&self, mode: Mode, pending: &[Chord]) -> Resolved {
+        let live = self
+            .bindings
+            .iter()
+            .filter(|b| b.mode == mode && !b.overridden);
+        let mut longer = false;
+        for b in live {
+            if b.keys == pending {
+                return Resolved::Action(b.action);
+            }
+            longer |= b.keys.starts_with(pending);
+        }
+        if longer {
+            Resolved::Pending
+        } else {
+            Resolved::Unbound
+        }
+    }
+
+    /// Human-readable problems with the binding table: the same keys bound twice in a mode, and
+    /// bindings that can never fire because a shorter binding is a prefix of them.
+    pub fn audit(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (i, b) in self.bindings.iter().enumerate() {
+            let keys = format_keys(&b.keys);
+            let mode = b.mode.name();
+            if let Some(winner) = self.bindings[i + 1..]
+                .iter()
+                .find(|w| w.mode == b.mode && w.keys == b.keys)
+                && winner.action != b.action
+            {
+                problems.push(format!(
+                    "{mode}: {keys} bound to both {} and {}; {} wins",
+                    b.action.name(),
+                    winner.action.name(),
+                    winner.action.name()
+                ));
+            }
+            if b.overridden {
+                continue;
+            }
+            for s in &self.bindings {
+                if s.mode == b.mode
+                    && !s.overridden
+                    && s.keys.len() < b.keys.len()
+                    && b.keys.starts_with(&s.keys)
+                {
+                    problems.push(format!(
+                        "{mode}: {} ({}) shadows {keys} ({}); {} wins",
+                        format_keys(&s.keys),
+                        s.action.name(),
+                        b.action.name(),
+                        s.action.name()
+                    ));
+                }
+            }
+        }
+        problems
+    }
+
+    /// The audit's problems, to show as warnings; with `strict` (`--strict-keys`), an error
+    /// listing them instead.
+    pub fn check(&self, strict: bool) -> Result<Vec<String>> {
+        let problems = self.audit();
+        if strict && !problems.is_empty() {
+            bail!("conflicting key bindings:\n  {}", problems.join("\n  "));
+        }
+        Ok(problems)
+    }
+}
+
+fn parse_binding(spec: &str) -> Result<Binding> {
+    let (lhs, action) = spec
+        .rsplit_once('=')
+        .ok_or_else(|| anyhow!("expected MODE:KEYS=ACTION"))?;
+    let (mode, keys) = lhs
+        .split_once(':')
+        .and_then(|(m, k)| Some((Mode::parse(m)?, k)))
+        .unwrap_or((Mode::Normal, lhs));
+    let action = Action::parse(action).ok_or_else(|| {
+        let names: Vec<_> = Action::ALL.iter().map(|a| a.name()).collect();
+        anyhow!("unknown action {action:?} (one of: {})", names.join(", "))
+    })?;
+    Ok(Binding {
+        mode,
+        keys: parse_keys(keys)?,
+        action,
+        source: Source::User,
+        overridden: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(c: char) -> Chord {
+        Chord {
+            code: KeyCode::Char(c),
+            mods: KeyModifiers::NONE,
+        }
+    }
+
+    fn user(bindings: &[&str]) -> Keymap {
+        let specs: Vec<String> = bindings.iter().map(|b| b.to_string()).collect();
+        Keymap::new(&specs).unwrap()
+    }
+
+    #[test]
+    fn parses_plain_keys_and_groups() {
+        assert_eq!(parse_keys("gg").unwrap(), [key('g'), key('g')]);
+        assert_eq!(
+            parse_keys("<C-d><M-a><S-Tab><Space><lt>").unwrap(),
+            [
+                Chord {
+                    code: KeyCode::Char('d'),
+                    mods: KeyModifiers::CONTROL
+                },
+                Chord {
+                    code: KeyCode::Char('a'),
+                    mods: KeyModifiers::ALT
+                },
+                Chord {
+                    code: KeyCode::BackTab,
+                    mods: KeyModifiers::NONE
+                },
+                key(' '),
+                key('<'),
+            ]
+        );
+        assert_eq!(parse_keys("<F5>").unwrap()[0].code, KeyCode::F(5));
+        // Display writes keys back in the same notation
+        assert_eq!(
+            format_keys(&parse_keys("g<C-d><lt>x").unwrap()),
+            "g<C-d><lt>x"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_key_specs() {
+        assert!(parse_keys("").is_err());
+        assert!(parse_keys("<Nope>").is_err());
+        let err = parse_keys("a<C-d").unwrap_err().to_string();
+        assert!(err.contains("unclosed <C-d"), "{err}");
+        assert!(parse_keys("<").is_err());
+    }
+
+    #[test]
+    fn resolve_waits_on_prefixes() {
+        let keymap = user(&[]);
+        assert!(matches!(
+            keymap.resolve(Mode::Normal, &[key('g')]),
+            Resolved::Pending
+        ));
+        assert!(matches!(
+            keymap.resolve(Mode::Normal, &[key('g'), key('g')]),
+            Resolved::Action(Action::MoveTop)
+        ));
+        assert!(matches!(
+            keymap.resolve(Mode::Normal, &[key('g'), key('x')]),
+            Resolved::Unbound
+        ));
+        // Modes have their own tables
+        assert!(matches!(
+            keymap.resolve(Mode::Line, &[key('j')]),
+            Resolved::Action(Action::LineDown)
+        ));
+        assert!(matches!(
+            keymap.resolve(Mode::Line, &[key('g')]),
+            Resolved::Unbound
+        ));
+    }
+
+    #[test]
+    fn user_bindings_override_defaults() {
+        let keymap = user(&["j=up", "line:<C-n>=line-down"]);
+        assert!(matches!(
+            keymap.resolve(Mode::Normal, &[key('j')]),
+            Resolved::Action(Action::MoveUp)
+        ));
+        let ctrl_n = parse_keys("<C-n>").unwrap();
+        assert!(matches!(
+            keymap.resolve(Mode::Line, &ctrl_n),
+            Resolved::Action(Action::LineDown)
+        ));
+        assert!(
+            keymap
+                .bindings()
+                .iter()
+                .any(|b| b.keys == [key('j')] && b.source == Source::Default && b.overridden)
+        );
+        assert!(Keymap::new(&["j=nope".to_string()]).is_err());
+    }
+
+    #[test]
+    fn audit_reports_duplicates_and_shadowed_sequences() {
+        assert!(user(&[]).audit().is_empty());
+        // Rebinding to the same action is no conflict
+        assert!(user(&["j=down"]).audit().is_empty());
+
+        let problems = user(&["j=up"]).audit();
+        assert_eq!(problems, ["normal: j bound to both down and up; up wins"]);
+
+        let problems = user(&["g=bottom"]).audit();
+        assert_eq!(
+            problems,
+            ["normal: g (bottom) shadows gg (top); bottom wins"]
+        );
+        // A shadowed sequence never fires
+        assert!(matches!(
+            user(&["g=bottom"]).resolve(Mode::Normal, &[key('g')]),
+            Resolved::Action(Action::MoveBottom)
+        ));
+    }
+
+    #[test]
+    fn strict_keys_turns_problems_into_an_error() {
+        let keymap = user(&["g=bottom"]);
+        assert_eq!(keymap.check(false).unwrap().len(), 1);
+        let err = keymap.check(true).unwrap_err().to_string();
+        assert!(err.starts_with("conflicting key bindings:"), "{err}");
+        assert!(err.contains("g (bottom) shadows gg (top)"));
+        assert!(user(&[]).check(true).unwrap().is_empty());
+    }
+}
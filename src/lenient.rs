@@ -0,0 +1,195 @@
+//! `--lenient`: recover what we can from patches mangled in transit (truncated by a mail
+//! server, leading spaces lost or long lines wrapped by copy-paste).
+//!
+//! Every repair is driven by the `@@` counts: a body that comes up short is truncated, stray
+//! lines that fit the counts as context lost their leading space, and stray lines that would
+//! overflow the counts are the tails of wrapped lines.
+
+use crate::{FileDiff, Hunk, count_body, make_hunk_preview, parse_hunk_header, recount_header};
+
+/// Why a repaired hunk can't be trusted to be what its author wrote.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Damage {
+    /// The body ends before the header's counts are reached.
+    Truncated,
+    /// Lines without a diff prefix were taken for the tails of wrapped lines and rejoined.
+    Wrapped,
+}
+
+impl Damage {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Damage::Truncated => "truncated",
+            Damage::Wrapped => "wrapped",
+        }
+    }
+}
+
+/// What repairing one hunk involved.
+#[derive(Debug, Default)]
+struct Repair {
+    /// Context lines that got their leading space back.
+    spaced: usize,
+    /// Wrapped line tails joined onto the line before them.
+    joined: usize,
+    /// Header counts vs. what the body held, when it came up short.
+    short: Option<((usize, usize), (usize, usize))>,
+}
+
+fn repair_hunk(h: &mut Hunk) -> Repair {
+    let mut repair = Repair::default();
+    let Some((range, _)) = parse_hunk_header(&h.header) else {
+        return repair;
+    };
+    let stray = |l: &String| !l.is_empty() && !l.starts_with([' ', '+', '-', '\\']);
+    let strays = h.lines.iter().filter(|l| stray(l)).count();
+    if strays > 0 {
+        let (old, new) = count_body(&h.lines);
+        if old + strays <= range.old_len && new + strays <= range.new_len {
+            for l in h.lines.iter_mut().filter(|l| stray(l)) {
+                l.insert(0, ' ');
+                repair.spaced += 1;
+            }
+        } else {
+            let mut joined: Vec<String> = Vec::with_capacity(h.lines.len());
+            for l in h.lines.drain(..) {
+                match joined.last_mut() {
+                    // Wrapping breaks at a space and eats it; put one back
+                    Some(prev) if stray(&l) => {
+                        if !prev.ends_with(char::is_whitespace) {
+                            prev.push(' ');
+                        }
+                        prev.push_str(&l);
+                        repair.joined += 1;
+                    }
+                    None if stray(&l) => {
+                        joined.push(format!(" {l}"));
+                        repair.spaced += 1;
+                    }
+                    _ => joined.push(l),
+                }
+            }
+            h.lines = joined;
+        }
+    }
+    let (old, new) = count_body(&h.lines);
+    if old < range.old_len || new < range.new_len {
+        repair.short = Some(((range.old_len, range.new_len), (old, new)));
+        // Keep the hunk well-formed so it can still be written if the user insists
+        h.header = recount_header(&h.header, &h.lines);
+    }
+    h.damage = if repair.joined > 0 {
+        Some(Damage::Wrapped)
+    } else if repair.short.is_some() {
+        Some(Damage::Truncated)
+    } else {
+        None
+    };
+    if repair.spaced > 0 || h.damage.is_some() {
+        h.display = make_hunk_preview(&h.header, &h.lines);
+    }
+    repair
+}
+
+/// Repair every hunk in place. Returns how many hunks needed it, and a description of what was
+/// done: a summary line first, then one line per repaired hunk (empty when nothing was).
+pub fn repair(files: &[FileDiff], hunks: &mut [Hunk]) -> (usize, Vec<String>) {
+    let mut details = Vec::new();
+    let (mut truncated, mut wrapped, mut spaced) = (0, 0, 0);
+    for h in hunks.iter_mut().filter(|h| !h.header_only) {
+        let original = h.header.clone();
+        let repair = repair_hunk(h);
+        let mut what = Vec::new();
+        if repair.spaced > 0 {
+            what.push(format!(
+                "{} context line(s) missing their leading space",
+                repair.spaced
+            ));
+            spaced += repair.spaced;
+        }
+        if repair.joined > 0 {
+            what.push(format!("{} wrapped line(s) rejoined", repair.joined));
+            wrapped += 1;
+        }
+        if let Some(((want_old, want_new), (old, new))) = repair.short {
+            what.push(format!(
+                "truncated: body has {old}/{new} of {want_old}/{want_new} old/new lines"
+            ));
+            truncated += 1;
+        }
+        if !what.is_empty() {
+            details.push(format!(
+                "{} {}: {}{}",
+                files[h.file_idx].file_label,
+                original,
+                what.join(", "),
+                if h.damage.is_some() {
+                    " (unreliable)"
+                } else {
+                    ""
+                }
+            ));
+        }
+    }
+    if details.is_empty() {
+        return (0, details);
+    }
+    let mut summary = vec![format!(
+        "Lenient parsing repaired {} hunk(s): {truncated} truncated, {wrapped} with wrapped lines, \
+         {spaced} context line(s) missing their leading space",
+        details.len()
+    )];
+    let repaired = details.len();
+    summary.extend(details);
+    (repaired, summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_unified_diff;
+
+    /// The one hunk of a patch touching `f.txt`, before any repair.
+    fn hunk(header: &str, body: &[&str]) -> Hunk {
+        let patch = format!(
+            "diff --git a/f.txt b/f.txt\n--- a/f.txt\n+++ b/f.txt\n{header}\n{}\n",
+            body.join("\n")
+        );
+        let (_, mut hunks) = parse_unified_diff(&patch).unwrap();
+        assert_eq!(hunks.len(), 1);
+        hunks.remove(0)
+    }
+
+    #[test]
+    fn lines_that_fit_as_context_get_their_space_back() {
+        let mut h = hunk("@@ -1,3 +1,3 @@", &["one", "-two", "+TWO", "three"]);
+        let repair = repair_hunk(&mut h);
+        assert_eq!(repair.spaced, 2);
+        assert_eq!(repair.joined, 0);
+        assert!(repair.short.is_none());
+        assert_eq!(h.lines, [" one", "-two", "+TWO", " three"]);
+        assert_eq!(h.damage, None);
+    }
+
+    #[test]
+    fn overflowing_lines_are_rejoined_as_wrapped() {
+        let mut h = hunk(
+            "@@ -1,2 +1,2 @@",
+            &[" one", "-a line that got", "wrapped", "+a line"],
+        );
+        let repair = repair_hunk(&mut h);
+        assert_eq!(repair.joined, 1);
+        assert_eq!(h.lines, [" one", "-a line that got wrapped", "+a line"]);
+        assert_eq!(h.header, "@@ -1,2 +1,2 @@");
+        assert_eq!(h.damage, Some(Damage::Wrapped));
+    }
+
+    #[test]
+    fn short_bodies_are_truncated_and_recounted() {
+        let mut h = hunk("@@ -1,5 +1,5 @@ fn main", &[" one", "-two", "+TWO"]);
+        let repair = repair_hunk(&mut h);
+        assert_eq!(repair.short, Some(((5, 5), (2, 2))));
+        assert_eq!(h.header, "@@ -1,2 +1,2 @@ fn main");
+        assert_eq!(h.damage, Some(Damage::Truncated));
+    }
+}
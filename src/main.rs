@@ -1,6 +1,22 @@
+mod anon;
+mod blocks;
+mod budget;
+mod dirdiff;
+mod expr;
+mod git;
+mod hook;
+mod keys;
+mod lenient;
+mod picker;
+mod regen;
+mod report;
+mod session;
+mod source;
+
 use anyhow::{Context, Result, anyhow};
-use clap::{Parser, arg, command};
+use clap::Parser;
 use crossterm::{
+    clipboard::CopyToClipboard,
     event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
@@ -9,14 +25,31 @@ use ratatui::widgets::Clear;
 use ratatui::{
     Terminal,
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
+    layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
+    widgets::{
+        Block, Borders, List, ListItem, ListState, Paragraph, Scrollbar, ScrollbarOrientation,
+        ScrollbarState, Wrap,
+    },
 };
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap};
 use std::fs;
-use std::io::{self, Write};
-use std::path::PathBuf;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use anon::Anonymizer;
+use budget::{RenderBudget, RenderFeatures};
+use expr::Expr;
+use hook::PostSave;
+use lenient::Damage;
+use picker::FilePicker;
+use session::Session;
+use source::Sources;
+
+use keys::{Action, Chord, Keymap, Mode, Resolved};
 
 /// Minimal unified-diff hunk representation and file headers
 #[derive(Debug, Clone)]
@@ -26,6 +59,71 @@ struct Hunk {
     file_idx: usize,    // index into files[]
     marked: bool,
     display: String, // short preview for list
+    // Body line indices left out of the output (line-level selection)
+    excluded: BTreeSet<usize>,
+    // Stands for a file without any hunk (mode change, rename, binary, `git diff -D` deletion):
+    // selecting it writes just the file headers
+    header_only: bool,
+    apply: ApplyState,
+    // Body lines an earlier `a` already put in the tree while others were excluded; applying
+    // again sends only what's left, on top of them
+    applied_lines: BTreeSet<usize>,
+    // Set by `--lenient` when the hunk had to be repaired in a way that may not match the original
+    damage: Option<Damage>,
+    // The user confirmed writing (and applying) this unreliable hunk anyway
+    unreliable_ok: bool,
+    // With `--repo-root`: the lines the hunk expects aren't in the working tree any more
+    conflict: bool,
+    // Where the hunk's `@@` line (for header-only entries: its file's first header line) starts
+    // in the input: 0-based line number and byte offset, as read from disk
+    line_offset: usize,
+    byte_offset: usize,
+}
+
+/// What happened when the hunk was sent to `git apply`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ApplyState {
+    NotApplied,
+    /// Applied, shifted by this many lines from where the hunk header put it (0 for an exact
+    /// fit); a shift means the tree had drifted from the patch.
+    Applied(isize),
+    Failed,
+}
+
+impl Hunk {
+    /// Whether the hunk may be written and applied: it's reliable, or the user said so.
+    fn writable(&self) -> bool {
+        self.damage.is_none() || self.unreliable_ok
+    }
+}
+
+impl ApplyState {
+    fn is_applied(self) -> bool {
+        matches!(self, ApplyState::Applied(_))
+    }
+}
+
+/// Stable content hash identifying a hunk across runs (FNV-1a over the file path and body).
+fn hunk_id(file: &FileDiff, hunk: &Hunk) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |bytes: &[u8]| {
+        for &b in bytes {
+            hash ^= u64::from(b);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    feed(file.path().as_bytes());
+    for l in &hunk.lines {
+        feed(b"\n");
+        feed(l.as_bytes());
+    }
+    if hunk.header_only {
+        for l in &file.headers {
+            feed(b"\n");
+            feed(l.as_bytes());
+        }
+    }
+    format!("{hash:016x}")
 }
 
 #[derive(Debug, Clone)]
@@ -37,7 +135,94 @@ struct FileDiff {
     file_label: String, // e.g. "a/foo.c → b/foo.c"
 }
 
-/// Very simple unified-diff parser that’s resilient to extra metadata sections.
+impl FileDiff {
+    /// Repository-relative path of the file: the new side, or the old one for deletions.
+    fn path(&self) -> String {
+        let side = |prefix: &str| {
+            self.headers
+                .iter()
+                .find_map(|l| l.strip_prefix(prefix))
+                .filter(|p| *p != "/dev/null")
+        };
+        if let Some(p) = side("+++ ").or_else(|| side("--- ")) {
+            return strip_side_prefix(strip_timestamp(p)).to_string();
+        }
+        self.headers
+            .first()
+            .and_then(|l| start_line_path(l))
+            .map_or_else(
+                || self.file_label.clone(),
+                |p| strip_side_prefix(p).to_string(),
+            )
+    }
+
+    /// The headers written before the file's hunks and those written after them: svn's
+    /// `Property changes on:` block, with the blank line before it, goes last.
+    fn split_headers(&self) -> (&[String], &[String]) {
+        let Some(mut at) = self
+            .headers
+            .iter()
+            .position(|l| l.starts_with("Property changes on: "))
+        else {
+            return (&self.headers, &[]);
+        };
+        if at > 0 && self.headers[at - 1].is_empty() {
+            at -= 1;
+        }
+        self.headers.split_at(at)
+    }
+
+    /// Path of the file before the change, from the `---` line; `None` for new files.
+    fn old_path(&self) -> Option<String> {
+        let p = self.headers.iter().find_map(|l| l.strip_prefix("--- "))?;
+        let p = strip_timestamp(p);
+        (p != "/dev/null").then(|| strip_side_prefix(p).to_string())
+    }
+
+    /// Blob id of the pre-image from git's `index OLD..NEW` line, unless the file is new.
+    fn old_blob(&self) -> Option<String> {
+        let range = self.headers.iter().find_map(|l| l.strip_prefix("index "))?;
+        let (old, _) = range.split_once("..")?;
+        (!old.bytes().all(|b| b == b'0')).then(|| old.to_string())
+    }
+}
+
+/// Drop what follows the path on a `---`/`+++` line: plain `diff -u` and Mercurial put a
+/// timestamp there, Subversion a revision, always after a tab (expanded to spaces on input).
+fn strip_timestamp(path: &str) -> &str {
+    path.split("    ").next().unwrap_or(path)
+}
+
+/// Whether `line` opens a new file section: `diff --git` for git, `Index:` for Subversion and
+/// `diff -r` for Mercurial without `--git`.
+fn is_file_start(line: &str) -> bool {
+    line.starts_with("diff --git ") || line.starts_with("Index: ") || line.starts_with("diff -r ")
+}
+
+/// The path named on a file section's first line (the new side for `diff --git`).
+fn start_line_path(line: &str) -> Option<&str> {
+    if let Some(rest) = line.strip_prefix("diff --git ") {
+        rest.split_whitespace().nth(1)
+    } else if let Some(path) = line.strip_prefix("Index: ") {
+        Some(path.trim())
+    } else if line.starts_with("diff -r ") {
+        // `diff -r REV [-r REV] PATH`
+        line.split_whitespace().last()
+    } else {
+        None
+    }
+}
+
+/// Drop git's `a/` / `b/` path prefix.
+fn strip_side_prefix(path: &str) -> &str {
+    path.strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path)
+}
+
+/// Very simple unified-diff parser that’s resilient to extra metadata sections. Lines are
+/// normalized as they're read (line endings dropped, tabs expanded to four spaces), while hunk
+/// offsets refer to `input` as given.
 fn parse_unified_diff(input: &str) -> Result<(Vec<FileDiff>, Vec<Hunk>)> {
     let mut files: Vec<FileDiff> = Vec::new();
     let mut hunks: Vec<Hunk> = Vec::new();
@@ -50,13 +235,17 @@ fn parse_unified_diff(input: &str) -> Result<(Vec<FileDiff>, Vec<Hunk>)> {
     let mut capturing_hunk = false;
     let mut hunk_header = String::new();
     let mut hunk_lines: Vec<String> = Vec::new();
+    // (line, byte) where the open hunk and each file section start
+    let mut hunk_start = (0, 0);
+    let mut file_starts: Vec<(usize, usize)> = Vec::new();
 
     // A small helper to flush any open hunk
-    let mut finish_hunk = |files: &mut Vec<FileDiff>,
-                           hunks: &mut Vec<Hunk>,
-                           current_file: Option<usize>,
-                           hunk_header: &mut String,
-                           hunk_lines: &mut Vec<String>| {
+    let finish_hunk = |files: &mut Vec<FileDiff>,
+                       hunks: &mut Vec<Hunk>,
+                       current_file: Option<usize>,
+                       hunk_header: &mut String,
+                       hunk_lines: &mut Vec<String>,
+                       (line_offset, byte_offset): (usize, usize)| {
         if !hunk_header.is_empty() {
             let file_idx = current_file.expect("hunk without file");
             let preview = make_hunk_preview(hunk_header, hunk_lines);
@@ -67,13 +256,23 @@ fn parse_unified_diff(input: &str) -> Result<(Vec<FileDiff>, Vec<Hunk>)> {
                 file_idx,
                 marked: false,
                 display: preview,
+                excluded: BTreeSet::new(),
+                header_only: false,
+                apply: ApplyState::NotApplied,
+                applied_lines: BTreeSet::new(),
+                damage: None,
+                unreliable_ok: false,
+                conflict: false,
+                line_offset,
+                byte_offset,
             });
             files[file_idx].hunks.push(idx);
         }
     };
 
-    // Emit new FileDiff from pending_headers when we see `diff --git` for a new file
-    let mut start_new_file = |files: &mut Vec<FileDiff>, pending: &mut Vec<String>| {
+    // Emit new FileDiff from pending_headers when we see `diff --git` (or the svn/hg
+    // equivalent, see `is_file_start`) for a new file
+    let start_new_file = |files: &mut Vec<FileDiff>, pending: &mut Vec<String>| {
         let label = extract_file_label(pending);
         files.push(FileDiff {
             headers: std::mem::take(pending),
@@ -83,8 +282,16 @@ fn parse_unified_diff(input: &str) -> Result<(Vec<FileDiff>, Vec<Hunk>)> {
         files.len() - 1
     };
 
-    for line in input.lines() {
-        if line.starts_with("diff --git ") {
+    let mut byte = 0;
+    for (line_no, raw) in input.split_inclusive('\n').enumerate() {
+        let at = (line_no, byte);
+        byte += raw.len();
+        let line = match raw.strip_suffix('\n') {
+            Some(l) => l.strip_suffix('\r').unwrap_or(l),
+            None => raw,
+        };
+        let line = &*line.replace('\t', "    ");
+        if is_file_start(line) {
             // If a hunk is open, close it
             if capturing_hunk {
                 finish_hunk(
@@ -93,20 +300,17 @@ fn parse_unified_diff(input: &str) -> Result<(Vec<FileDiff>, Vec<Hunk>)> {
                     current_file,
                     &mut hunk_header,
                     &mut hunk_lines,
+                    hunk_start,
                 );
                 capturing_hunk = false;
             }
-            // If we already have a current file, finalize its headers (already stored)
-            if let Some(_) = current_file {
-                // nothing to flush besides hunks; headers already on that file
-            }
             // Start collecting headers for the *new* file
             // First, if previous pending headers exist without having been turned into a file, that’s weird;
             // but we’ll start fresh.
             pending_headers = vec![line.to_string()];
-            current_file = None; // will be set when we hit first "@@" or when we encounter ---/+++; for safety we create file immediately
             // We can eagerly create the file now so any subsequent headers attach to it.
             let idx = start_new_file(&mut files, &mut pending_headers);
+            file_starts.push(at);
             current_file = Some(idx);
         } else if line.starts_with("@@ ") || line.starts_with("@@-") || line.starts_with("@@+") {
             // starting a hunk
@@ -118,17 +322,41 @@ fn parse_unified_diff(input: &str) -> Result<(Vec<FileDiff>, Vec<Hunk>)> {
                     current_file,
                     &mut hunk_header,
                     &mut hunk_lines,
+                    hunk_start,
                 );
-                capturing_hunk = false;
             }
             if current_file.is_none() {
                 // We didn’t see diff --git for some reason; start a synthetic file bucket
                 let idx = start_new_file(&mut files, &mut pending_headers);
+                file_starts.push(at);
                 current_file = Some(idx);
             }
             capturing_hunk = true;
+            hunk_start = at;
             hunk_header = line.to_string();
             hunk_lines.clear();
+        } else if line.starts_with("Property changes on: ") {
+            // svn's property block follows the file's hunks, after a blank line that isn't
+            // part of the last hunk; keep the lot with the file's headers
+            let mut block = vec![line.to_string()];
+            if capturing_hunk {
+                if hunk_lines.last().is_some_and(|l| l.is_empty()) {
+                    block.insert(0, hunk_lines.pop().unwrap_or_default());
+                }
+                finish_hunk(
+                    &mut files,
+                    &mut hunks,
+                    current_file,
+                    &mut hunk_header,
+                    &mut hunk_lines,
+                    hunk_start,
+                );
+                capturing_hunk = false;
+            }
+            match current_file {
+                Some(idx) => files[idx].headers.extend(block),
+                None => pending_headers.extend(block),
+            }
         } else {
             // Either header-ish or hunk body
             if capturing_hunk {
@@ -136,10 +364,9 @@ fn parse_unified_diff(input: &str) -> Result<(Vec<FileDiff>, Vec<Hunk>)> {
                 hunk_lines.push(line.to_string());
             } else {
                 // file headers: accumulate onto current file
-                if current_file.is_none() {
-                    pending_headers.push(line.to_string());
-                } else {
-                    files[current_file.unwrap()].headers.push(line.to_string());
+                match current_file {
+                    Some(idx) => files[idx].headers.push(line.to_string()),
+                    None => pending_headers.push(line.to_string()),
                 }
             }
         }
@@ -153,12 +380,90 @@ fn parse_unified_diff(input: &str) -> Result<(Vec<FileDiff>, Vec<Hunk>)> {
             current_file,
             &mut hunk_header,
             &mut hunk_lines,
+            hunk_start,
         );
     }
 
+    // Files without hunks still carry a change in their headers; give each a selectable entry
+    for (file_idx, f) in files.iter_mut().enumerate() {
+        if f.hunks.is_empty() {
+            let (line_offset, byte_offset) = file_starts[file_idx];
+            f.hunks.push(hunks.len());
+            hunks.push(Hunk {
+                header: String::new(),
+                lines: Vec::new(),
+                file_idx,
+                marked: false,
+                display: format!("({})", header_only_kind(&f.headers).describe()),
+                excluded: BTreeSet::new(),
+                header_only: true,
+                apply: ApplyState::NotApplied,
+                applied_lines: BTreeSet::new(),
+                damage: None,
+                unreliable_ok: false,
+                conflict: false,
+                line_offset,
+                byte_offset,
+            });
+        }
+    }
+
     Ok((files, hunks))
 }
 
+/// What a file section without hunks changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HeaderOnlyKind {
+    Binary,
+    /// `git diff -D` / `--irreversible-delete`: a deletion whose old content was left out, which
+    /// `git apply` refuses and which can't be reversed.
+    DeletionWithoutPreimage,
+    Deletion,
+    Creation,
+    Rename,
+    ModeChange,
+    Metadata,
+}
+
+impl HeaderOnlyKind {
+    fn describe(self) -> &'static str {
+        match self {
+            HeaderOnlyKind::Binary => "binary",
+            HeaderOnlyKind::DeletionWithoutPreimage => "deletion, no preimage",
+            HeaderOnlyKind::Deletion => "empty file deleted",
+            HeaderOnlyKind::Creation => "empty file created",
+            HeaderOnlyKind::Rename => "rename",
+            HeaderOnlyKind::ModeChange => "mode change",
+            HeaderOnlyKind::Metadata => "metadata only",
+        }
+    }
+}
+
+fn header_only_kind(headers: &[String]) -> HeaderOnlyKind {
+    let has = |prefix: &str| headers.iter().any(|l| l.starts_with(prefix));
+    if has("GIT binary patch")
+        || has("Binary files ")
+        || has("Cannot display: file marked as a binary type")
+    {
+        HeaderOnlyKind::Binary
+    } else if has("deleted file mode") {
+        // An empty file's deletion has the empty blob as preimage; anything else lost its content
+        if has("index e69de29") {
+            HeaderOnlyKind::Deletion
+        } else {
+            HeaderOnlyKind::DeletionWithoutPreimage
+        }
+    } else if has("new file mode") {
+        HeaderOnlyKind::Creation
+    } else if has("rename from") || has("copy from") {
+        HeaderOnlyKind::Rename
+    } else if has("old mode") {
+        HeaderOnlyKind::ModeChange
+    } else {
+        HeaderOnlyKind::Metadata
+    }
+}
+
 fn extract_file_label(headers: &[String]) -> String {
     // Try to synthesize something like "a/foo → b/foo" using ---/+++ or the diff --git line.
     let mut from = String::new();
@@ -171,6 +476,11 @@ fn extract_file_label(headers: &[String]) -> String {
                 from = parts[2].to_string();
                 to = parts[3].to_string();
             }
+        } else if l.starts_with("Index: ") || l.starts_with("diff -r ") {
+            // svn and hg name the file once, without a/ b/ sides
+            let path = start_line_path(l).unwrap_or_default();
+            from = path.to_string();
+            to = path.to_string();
         } else if l.starts_with("--- ") {
             from = l.trim_start_matches("--- ").to_string();
         } else if l.starts_with("+++ ") {
@@ -202,6 +512,539 @@ fn make_hunk_preview(header: &str, lines: &[String]) -> String {
     }
 }
 
+/// Files that appear more than once in the patch (same `file_label`), as (label, file indices).
+/// Concatenated patches can repeat a file, and `git apply` rejects the repeated headers.
+fn find_duplicate_files(files: &[FileDiff]) -> Vec<(String, Vec<usize>)> {
+    let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+    let mut by_label: HashMap<&str, usize> = HashMap::new();
+    for (fidx, f) in files.iter().enumerate() {
+        match by_label.get(f.file_label.as_str()) {
+            Some(&group) => groups[group].1.push(fidx),
+            None => {
+                by_label.insert(&f.file_label, groups.len());
+                groups.push((f.file_label.clone(), vec![fidx]));
+            }
+        }
+    }
+    groups.retain(|(_, idxs)| idxs.len() > 1);
+    groups
+}
+
+/// Whether any two of the hunks claim the same old-file lines, going by their headers.
+fn hunks_overlap(hunks: &[Hunk], idxs: &[usize]) -> bool {
+    let mut ranges: Vec<(usize, usize)> = idxs
+        .iter()
+        .filter_map(|&h| parse_hunk_header(&hunks[h].header))
+        .map(|(r, _)| (r.old_start, r.old_start + r.old_len.max(1)))
+        .collect();
+    ranges.sort_unstable();
+    ranges.windows(2).any(|w| w[1].0 < w[0].1)
+}
+
+/// Move the hunks of every repeated file entry under its first occurrence, ordered by old start
+/// line, so the output has a single block per file. The emptied entries stay in `files` but
+/// never reach the output. Files whose entries have overlapping hunks can't be combined into
+/// one valid section; they're left as they are and their labels returned.
+fn merge_duplicate_files(
+    files: &mut [FileDiff],
+    hunks: &mut [Hunk],
+    dupes: &[(String, Vec<usize>)],
+) -> Vec<String> {
+    let mut overlapping = Vec::new();
+    for (label, idxs) in dupes {
+        let all: Vec<usize> = idxs
+            .iter()
+            .flat_map(|&f| &files[f].hunks)
+            .copied()
+            .collect();
+        if hunks_overlap(hunks, &all) {
+            overlapping.push(label.clone());
+            continue;
+        }
+        let first = idxs[0];
+        for &dup in &idxs[1..] {
+            let moved = std::mem::take(&mut files[dup].hunks);
+            for &hidx in &moved {
+                hunks[hidx].file_idx = first;
+            }
+            files[first].hunks.extend(moved);
+        }
+        files[first].hunks.sort_by_key(|&hidx| {
+            parse_hunk_header(&hunks[hidx].header).map_or(0, |(r, _)| r.old_start)
+        });
+    }
+    overlapping
+}
+
+/// Number of added and removed lines in a hunk body.
+fn change_counts(lines: &[String]) -> (usize, usize) {
+    lines
+        .iter()
+        .fold((0, 0), |(adds, dels), l| match l.chars().next() {
+            Some('+') => (adds + 1, dels),
+            Some('-') => (adds, dels + 1),
+            _ => (adds, dels),
+        })
+}
+
+/// Which kind of change makes up most of a hunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Dominant {
+    Adds,
+    Dels,
+}
+
+impl Dominant {
+    /// The kind making up at least two thirds of the changed lines, if either does.
+    fn of((adds, dels): (usize, usize)) -> Option<Dominant> {
+        if adds > 0 && adds >= 2 * dels {
+            Some(Dominant::Adds)
+        } else if dels > 0 && dels >= 2 * adds {
+            Some(Dominant::Dels)
+        } else {
+            None
+        }
+    }
+
+    fn describe(self) -> &'static str {
+        match self {
+            Dominant::Adds => "mostly additions",
+            Dominant::Dels => "mostly deletions",
+        }
+    }
+}
+
+/// Line ranges from a `@@ -a,b +c,d @@` header. Omitted counts default to 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct HunkRange {
+    old_start: usize,
+    old_len: usize,
+    new_start: usize,
+    new_len: usize,
+}
+
+/// Split a hunk header into its ranges and the trailing section text (e.g. a function name).
+fn parse_hunk_header(header: &str) -> Option<(HunkRange, &str)> {
+    let rest = header.strip_prefix("@@")?.trim_start();
+    let end = rest.find("@@")?;
+    let (ranges, tail) = (&rest[..end], &rest[end + 2..]);
+    let mut parts = ranges.split_whitespace();
+    let old = parts.next()?.strip_prefix('-')?;
+    let new = parts.next()?.strip_prefix('+')?;
+    let parse_range = |r: &str| -> Option<(usize, usize)> {
+        match r.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((r.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_len) = parse_range(old)?;
+    let (new_start, new_len) = parse_range(new)?;
+    Some((
+        HunkRange {
+            old_start,
+            old_len,
+            new_start,
+            new_len,
+        },
+        tail,
+    ))
+}
+
+/// Format a hunk header the way git does, leaving out counts of 1.
+fn format_hunk_header(range: HunkRange, tail: &str) -> String {
+    let fmt = |start: usize, len: usize| {
+        if len == 1 {
+            start.to_string()
+        } else {
+            format!("{start},{len}")
+        }
+    };
+    format!(
+        "@@ -{} +{} @@{}",
+        fmt(range.old_start, range.old_len),
+        fmt(range.new_start, range.new_len),
+        tail
+    )
+}
+
+/// Count (old, new) lines in a hunk body. Empty lines count as context, like `git apply` does.
+fn count_body(lines: &[String]) -> (usize, usize) {
+    lines
+        .iter()
+        .fold((0, 0), |(old, new), l| match l.chars().next() {
+            Some(' ') | None => (old + 1, new + 1),
+            Some('-') => (old + 1, new),
+            Some('+') => (old, new + 1),
+            _ => (old, new),
+        })
+}
+
+/// Recompute the `@@` counts of a hunk from its body, keeping the start lines and section text.
+/// Headers that can't be parsed are returned unchanged.
+fn recount_header(header: &str, lines: &[String]) -> String {
+    match parse_hunk_header(header) {
+        Some((range, tail)) => {
+            let (old_len, new_len) = count_body(lines);
+            format_hunk_header(
+                HunkRange {
+                    old_len,
+                    new_len,
+                    ..range
+                },
+                tail,
+            )
+        }
+        None => header.to_string(),
+    }
+}
+
+/// The header and body a hunk contributes to the output once its excluded lines are applied:
+/// excluded `+` lines are dropped and excluded `-` lines become context. Returns `None` when no
+/// change line survives, since a hunk without changes is rejected by `git apply`. Header-only
+/// entries contribute an empty header and body.
+fn effective_hunk(h: &Hunk, recount: bool) -> Option<(String, Vec<String>)> {
+    if h.header_only {
+        return Some((String::new(), Vec::new()));
+    }
+    let mut lines = Vec::with_capacity(h.lines.len());
+    let mut dropped_prev = false;
+    for (i, l) in h.lines.iter().enumerate() {
+        // A "\ No newline" marker belongs to the line before it
+        if l.starts_with('\\') {
+            if !dropped_prev {
+                lines.push(l.clone());
+            }
+            continue;
+        }
+        dropped_prev = false;
+        if !h.excluded.contains(&i) {
+            lines.push(l.clone());
+        } else if let Some(rest) = l.strip_prefix('-') {
+            lines.push(format!(" {rest}"));
+        } else if !l.starts_with('+') {
+            lines.push(l.clone());
+        } else {
+            dropped_prev = true;
+        }
+    }
+    if !lines
+        .iter()
+        .any(|l| l.starts_with('+') || l.starts_with('-'))
+    {
+        return None;
+    }
+    let header = if recount || !h.excluded.is_empty() {
+        recount_header(&h.header, &lines)
+    } else {
+        h.header.clone()
+    };
+    Some((header, lines))
+}
+
+/// Indices of a hunk's `+` and `-` lines.
+fn changed_lines(h: &Hunk) -> impl Iterator<Item = usize> + '_ {
+    h.lines
+        .iter()
+        .enumerate()
+        .filter(|(_, l)| l.starts_with('+') || l.starts_with('-'))
+        .map(|(i, _)| i)
+}
+
+/// The hunk as it stands against a tree that already holds its `applied_lines`: those `-` lines
+/// are gone and those `+` lines are now context, so only the rest is left to apply.
+fn pending_hunk(h: &Hunk) -> Cow<'_, Hunk> {
+    if h.applied_lines.is_empty() {
+        return Cow::Borrowed(h);
+    }
+    let mut pending = h.clone();
+    pending.lines.clear();
+    pending.excluded.clear();
+    pending.applied_lines.clear();
+    let mut dropped_prev = false;
+    for (i, l) in h.lines.iter().enumerate() {
+        // A "\ No newline" marker belongs to the line before it
+        if l.starts_with('\\') && dropped_prev {
+            continue;
+        }
+        dropped_prev = false;
+        if h.applied_lines.contains(&i) {
+            match l.strip_prefix('+') {
+                Some(rest) => pending.lines.push(format!(" {rest}")),
+                None => dropped_prev = true,
+            }
+            continue;
+        }
+        if h.excluded.contains(&i) {
+            pending.excluded.insert(pending.lines.len());
+        }
+        pending.lines.push(l.clone());
+    }
+    pending.header = recount_header(&h.header, &pending.lines);
+    Cow::Owned(pending)
+}
+
+/// `--split-by-dir` group of a file: the first component of its path (its placeholder with
+/// `--anonymize`), or `root` for files at the top of the tree.
+fn top_dir(file: &FileDiff, anon: Option<&Anonymizer>) -> String {
+    match (file.path().split_once('/'), anon) {
+        (Some((dir, _)), Some(anon)) => anon.dir(dir),
+        (Some((dir, _)), None) => dir.to_string(),
+        (None, _) => "root".to_string(),
+    }
+}
+
+/// File in a `--split-by-dir` directory listing the patches written there, so a later run
+/// knows which ones are its own to remove.
+const SPLIT_MANIFEST: &str = ".patchers-split";
+
+/// Fail when two groups of `files` would share a `--split-by-dir` patch: top-level files
+/// go to `root.patch`, which a directory named `root` would claim too.
+fn check_split_names(files: &[FileDiff]) -> Result<()> {
+    let top_level = files.iter().any(|f| !f.path().contains('/'));
+    let root_dir = files.iter().any(|f| f.path().starts_with("root/"));
+    if top_level && root_dir {
+        return Err(anyhow!(
+            "--split-by-dir: files at the top of the tree and files under root/ would both \
+             be written to root.patch"
+        ));
+    }
+    Ok(())
+}
+
+/// The split patches an earlier run recorded in `dir`; none when there's no record.
+fn read_split_manifest(dir: &Path) -> BTreeSet<String> {
+    fs::read_to_string(dir.join(SPLIT_MANIFEST))
+        .map(|text| text.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Parse the `PATH:START-END` (or `PATH:LINE`) argument of `:hot`.
+fn parse_hot_range(arg: &str) -> Option<(&str, usize, usize)> {
+    let (path, range) = arg.rsplit_once(':')?;
+    let (start, end) = range.split_once('-').unwrap_or((range, range));
+    let (start, end): (usize, usize) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+    (!path.is_empty() && start <= end).then_some((path, start, end))
+}
+
+/// Whether a hunk's new-file lines overlap `start..=end`. A hunk that only removes lines
+/// touches the line it leaves them at.
+fn new_side_overlaps(h: &Hunk, start: usize, end: usize) -> bool {
+    if h.header_only {
+        return false;
+    }
+    let Some((range, _)) = parse_hunk_header(&h.header) else {
+        return false;
+    };
+    let first = range.new_start;
+    let last = range.new_start + range.new_len.max(1) - 1;
+    first <= end && start <= last
+}
+
+/// Things worth telling the user about a written patch: what was left out because nothing in
+/// it would change the target, and what in it won't apply cleanly.
+#[derive(Debug, Default, Clone)]
+struct WriteReport {
+    files_dropped: usize,
+    hunks_dropped: usize,
+    deletions_without_preimage: usize,
+    // Selected hunks repaired by `--lenient` that were left out pending confirmation
+    unreliable_held: usize,
+    // Written hunks whose context no longer matches the working tree
+    conflicts: usize,
+    // Hunks written with their input context because `--output-context` couldn't regenerate them
+    context_kept: usize,
+    // Each file written and its hunks as they went out
+    written: Vec<report::WrittenFile>,
+}
+
+impl WriteReport {
+    fn notes(&self) -> Vec<String> {
+        let mut notes = Vec::new();
+        if self.files_dropped > 0 {
+            notes.push(format!(
+                "{} file(s) dropped from output: no remaining changes",
+                self.files_dropped
+            ));
+        } else if self.hunks_dropped > 0 {
+            notes.push(format!(
+                "{} hunk(s) dropped from output: no remaining changes",
+                self.hunks_dropped
+            ));
+        }
+        if self.deletions_without_preimage > 0 {
+            notes.push(format!(
+                "{} deletion(s) without preimage (git diff -D): git apply refuses these and they can't be reversed",
+                self.deletions_without_preimage
+            ));
+        }
+        if self.conflicts > 0 {
+            notes.push(format!(
+                "{} hunk(s) likely to conflict with the working tree",
+                self.conflicts
+            ));
+        }
+        if self.context_kept > 0 {
+            notes.push(format!(
+                "{} hunk(s) kept their original context: file not found or hunk doesn't match it",
+                self.context_kept
+            ));
+        }
+        if self.unreliable_held > 0 {
+            notes.push(format!(
+                "{} unreliable (truncated or wrapped) hunk(s) left out",
+                self.unreliable_held
+            ));
+        }
+        notes
+    }
+}
+
+/// Read an `--order-file`: one repository path per line, blank lines and `#` comments ignored.
+/// Paths are taken as written; `a/x` names a file in a directory `a`, not git's old side.
+fn read_order_file(path: &Path) -> Result<Vec<String>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("failed to read order file {}", path.display()))?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// File indices in output order: files named in `wanted` first, in that order, then the rest
+/// in their original order.
+fn file_output_order(files: &[FileDiff], wanted: &[String]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..files.len()).collect();
+    order.sort_by_key(|&fidx| {
+        let path = files[fidx].path();
+        wanted.iter().position(|w| *w == path).unwrap_or(usize::MAX)
+    });
+    order
+}
+
+/// `--output-context`: how many context lines to regenerate hunks with, and the original
+/// contents of the files to take them from, by file index.
+struct Regen<'a> {
+    context: usize,
+    originals: HashMap<usize, &'a [String]>,
+}
+
+/// Serialize the chosen hunks back into a unified diff, grouped under their file headers and
+/// emitting files in `file_order`. Files without any chosen hunk are left out entirely, and so
+/// are files whose chosen hunks have no effective changes left (see [`effective_hunk`]).
+/// With `anon`, the paths in the file headers are replaced by placeholders.
+fn render_patch(
+    files: &[FileDiff],
+    hunks: &[Hunk],
+    file_order: &[usize],
+    include: impl Fn(&Hunk) -> bool,
+    recount: bool,
+    regen: Option<&Regen>,
+    anon: Option<&Anonymizer>,
+) -> (String, WriteReport) {
+    let mut out = String::new();
+    let mut report = WriteReport::default();
+    for &fidx in file_order {
+        let f = &files[fidx];
+        let selected: Vec<&Hunk> = f
+            .hunks
+            .iter()
+            .map(|&hidx| &hunks[hidx])
+            .filter(|h| include(h))
+            .collect();
+        if selected.is_empty() {
+            continue;
+        }
+        let bodies: Vec<(String, Vec<String>)> = selected
+            .iter()
+            .filter_map(|h| effective_hunk(h, recount))
+            .collect();
+        report.hunks_dropped += selected.len() - bodies.len();
+        if bodies.is_empty() {
+            report.files_dropped += 1;
+            continue;
+        }
+        // Header-only entries have nothing to regenerate
+        let bodies = match regen {
+            Some(regen) if !bodies[0].0.is_empty() => match regen.originals.get(&fidx) {
+                Some(original) => {
+                    let (bodies, kept) = regen::file_with_context(bodies, original, regen.context);
+                    report.context_kept += kept;
+                    bodies
+                }
+                None => {
+                    report.context_kept += bodies.len();
+                    bodies
+                }
+            },
+            _ => bodies,
+        };
+        if selected.iter().any(|h| h.header_only)
+            && header_only_kind(&f.headers) == HeaderOnlyKind::DeletionWithoutPreimage
+        {
+            report.deletions_without_preimage += 1;
+        }
+        // Write headers exactly as in the input, paths aside with `--anonymize`
+        let write_headers = |out: &mut String, headers: &[String]| {
+            for h in headers {
+                match anon {
+                    Some(anon) => out.push_str(&anon.header(h)),
+                    None => out.push_str(h),
+                }
+                out.push('\n');
+            }
+        };
+        let (leading, trailing) = f.split_headers();
+        write_headers(&mut out, leading);
+        for (header, lines) in &bodies {
+            if header.is_empty() {
+                continue;
+            }
+            out.push_str(header);
+            out.push('\n');
+            for l in lines {
+                out.push_str(l);
+                out.push('\n');
+            }
+        }
+        write_headers(&mut out, trailing);
+        report.written.push((fidx, bodies));
+    }
+    (out, report)
+}
+
+/// `--reflow`: rewrite every hunk header with counts recomputed from its body, ignoring selection.
+/// Returns how many headers actually changed.
+fn reflow_patch(
+    files: &[FileDiff],
+    hunks: &[Hunk],
+    file_order: &[usize],
+    output: &Path,
+    anon: Option<&Anonymizer>,
+) -> Result<usize> {
+    let fixed = hunks
+        .iter()
+        .filter(|h| !h.header_only)
+        .filter(|h| recount_header(&h.header, &h.lines) != h.header)
+        .count();
+    let (out, mut report) = render_patch(
+        files,
+        hunks,
+        file_order,
+        |h| h.damage.is_none(),
+        true,
+        None,
+        anon,
+    );
+    report.unreliable_held = hunks.iter().filter(|h| h.damage.is_some()).count();
+    fs::write(output, out).with_context(|| format!("failed to write {}", output.display()))?;
+    for note in report.notes() {
+        eprintln!("warning: {note}");
+    }
+    Ok(fixed)
+}
+
 #[derive(Parser, Debug)]
 #[command(
     version,
@@ -209,27 +1052,231 @@ fn make_hunk_preview(header: &str, lines: &[String]) -> String {
 )]
 struct Opts {
     /// Input patch file (unified diff)
-    input: PathBuf,
+    #[arg(required_unless_present_any = ["from_stash", "dir_diff"])]
+    input: Option<PathBuf>,
+    /// Take the input from `git stash show -p stash@{N}` in `--repo-root` (default: the latest
+    /// stash), to bring back part of a stash with `a`
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "0", conflicts_with = "input")]
+    from_stash: Option<usize>,
+    /// Take the input from comparing two directory trees, A (before) and B (after), instead of
+    /// from a patch file; no git needed
+    #[arg(long, num_args = 2, value_names = ["A", "B"], conflicts_with_all = ["input", "from_stash"])]
+    dir_diff: Option<Vec<PathBuf>>,
+    /// With `--dir-diff`, skip files and directories matching this glob (repeatable), e.g.
+    /// `target` or `*.log` for a name anywhere, `build/**` for a path
+    #[arg(long, value_name = "GLOB", requires = "dir_diff", conflicts_with_all = ["input", "from_stash"])]
+    exclude: Vec<String>,
     /// Output patch file to write whenever you press Space
     #[arg(short, long)]
     output: PathBuf,
+    /// Don't start the TUI: recompute every hunk's `@@` line counts from its body and write
+    /// all hunks to the output (fixes up hand-edited patches)
+    #[arg(long)]
+    reflow: bool,
+    /// Merge the hunks of files that appear more than once in the input into a single entry
+    #[arg(long)]
+    merge_dupes: bool,
+    /// Emit files in the order listed in this file (one path per line); files not listed
+    /// follow in their original order
+    #[arg(long, value_name = "FILE")]
+    order_file: Option<PathBuf>,
+    /// Add or override a key binding, as `[MODE:]KEYS=ACTION` (e.g. `x=toggle`,
+    /// `line:<C-n>=line-down`). Press `?` in the TUI to list modes, keys and actions
+    #[arg(long = "bind", value_name = "BINDING")]
+    bindings: Vec<String>,
+    /// Refuse to start when key bindings conflict or shadow each other
+    #[arg(long)]
+    strict_keys: bool,
+    /// Preselect the hunks matching a selection expression, e.g.
+    /// `path(src/**) and grep(TODO) and size<50` (see the readme for the predicates)
+    #[arg(long, value_name = "EXPR")]
+    expr: Option<String>,
+    /// Preselect hunks at startup; `all` leaves out hunks `--lenient` flagged as unreliable
+    #[arg(long, value_enum, value_name = "WHICH", conflicts_with = "expr")]
+    preselect: Option<Preselect>,
+    /// Preselect the hunks listed in a selection manifest written by `--save-selection`
+    #[arg(long, value_name = "FILE")]
+    load_selection: Option<PathBuf>,
+    /// Write the ids of the selected hunks to this manifest whenever the output is written
+    #[arg(long, value_name = "FILE")]
+    save_selection: Option<PathBuf>,
+    /// Whenever the output is written, also write the selected hunks split by top-level
+    /// directory into DIR: `src.patch`, `tests.patch`, … and `root.patch` for top-level files
+    #[arg(long, value_name = "DIR")]
+    split_by_dir: Option<PathBuf>,
+    /// Whenever the output is written, also write a review report to FILE: stats, the list of
+    /// selected hunks, and the filtered patch itself
+    #[arg(long, value_name = "FILE")]
+    report: Option<PathBuf>,
+    /// Replace the file paths in the output's headers with `file1`, `file2`, … for sharing a
+    /// patch without its paths
+    #[arg(long)]
+    anonymize: bool,
+    /// With `--anonymize`, write which path each placeholder stands for to FILE
+    #[arg(long, value_name = "FILE", requires = "anonymize")]
+    anonymize_map: Option<PathBuf>,
+    /// Don't start the TUI: write the preselected hunks to the output and exit
+    #[arg(long)]
+    batch: bool,
+    /// Repair truncated and mangled patches (lost leading spaces, wrapped lines) instead of
+    /// taking them as they are; repaired hunks that may not match the original are flagged
+    /// unreliable and only written after confirmation
+    #[arg(long)]
+    lenient: bool,
+    /// Repository that `a` applies hunks to (default: the current directory). When given,
+    /// hunks whose context no longer matches the working tree are flagged as likely conflicts
+    #[arg(long, value_name = "DIR")]
+    repo_root: Option<PathBuf>,
+    /// Apply hunks to the index (`git apply --cached`) instead of the working tree
+    #[arg(long)]
+    apply_cached: bool,
+    /// Remember marks and applied hunks in this file across runs
+    #[arg(long, value_name = "FILE")]
+    session: Option<PathBuf>,
+    /// Directory holding the original files, for the whole-file preview (`w`) of patches made
+    /// outside git; paths from the `---` lines are resolved against it
+    #[arg(long, value_name = "DIR")]
+    orig_dir: Option<PathBuf>,
+    /// Write hunks with exactly N lines of context, regenerated from the original files
+    /// (`--orig-dir`, or git's pre-image blobs under `--repo-root`)
+    #[arg(long, value_name = "N")]
+    output_context: Option<usize>,
+    /// Cut preview lines longer than N characters (display only; 0 shows them in full). `L`
+    /// shows the current hunk's lines in full
+    #[arg(long, value_name = "N", default_value_t = 300)]
+    max_line_len: usize,
+    /// Save and quit after SECS seconds without a key press (for demos and supervised
+    /// automation); the status bar counts down
+    #[arg(long, value_name = "SECS")]
+    auto_quit_after: Option<u64>,
+    /// How `Y` copies the current hunk's location: `colon` for `path:line`, `plus` for
+    /// `+line path`
+    #[arg(long, value_enum, value_name = "FORMAT", default_value = "colon")]
+    jump_format: JumpFormat,
+    /// On quitting the TUI, print how many hunks were looked at and selected and how long the
+    /// review took
+    #[arg(long)]
+    summary: bool,
+    /// Run this shell command in the background after every save; it gets PATCHERS_OUTPUT,
+    /// PATCHERS_INPUT and PATCHERS_SELECTED in its environment
+    #[arg(long, value_name = "CMD")]
+    post_save_cmd: Option<String>,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum Preselect {
+    All,
+}
+
+/// How `Y` formats a hunk's location for pasting into an editor command line.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+enum JumpFormat {
+    /// `path:line`, for VS Code (`code -g`), Sublime Text, Helix, …
+    Colon,
+    /// `+line path`, for vim, emacs, nano, …
+    Plus,
+}
+
+impl JumpFormat {
+    fn format(self, path: &str, line: usize) -> String {
+        match self {
+            JumpFormat::Colon => format!("{path}:{line}"),
+            JumpFormat::Plus => format!("+{line} {path}"),
+        }
+    }
+}
+
+/// What each row of the list stands for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+    Hunks,
+    /// One row per file; `order` then holds the first hunk of each file.
+    Files,
 }
 
 struct App {
     files: Vec<FileDiff>,
     hunks: Vec<Hunk>,
+    // `change_counts` of each hunk's body, which doesn't change once the TUI starts
+    counts: Vec<(usize, usize)>,
     // Flattened list of (file_idx, hunk_idx) to present in UI order
     order: Vec<usize>, // indices into hunks[]
+    // File indices in the order they are written out
+    file_order: Vec<usize>,
+    view: View,
     cursor: usize,
+    // Index into the current hunk's lines while choosing individual lines
+    line_cursor: Option<usize>,
     input_path: PathBuf,
     output_path: PathBuf,
+    repo_root: PathBuf,
+    // Compare hunks against the working tree under `repo_root`
+    check_tree: bool,
+    // The working-tree stamp each file's hunks were last checked against, so only files that
+    // changed since get read and searched again
+    tree_stamps: HashMap<usize, Option<(SystemTime, u64)>>,
+    apply_cached: bool,
+    session: Option<Session>,
+    // `--save-selection` manifest, rewritten along with the output
+    selection_path: Option<PathBuf>,
+    // `--report`, rewritten along with the output
+    report_path: Option<PathBuf>,
+    // `--anonymize`: paths in everything written are replaced by placeholders
+    anonymizer: Option<Anonymizer>,
+    // `--split-by-dir`, and the per-directory patches written there (by this run or, as
+    // recorded in the directory, an earlier one)
+    split_dir: Option<PathBuf>,
+    split_written: BTreeSet<String>,
+    post_save: Option<PostSave>,
+    // Show the current hunk inside its whole original file
+    whole_file: bool,
+    // Show the file's raw headers above the hunk in the preview
+    show_headers: bool,
+    // Preview removed lines next to their replacements, with changed words highlighted
+    block_view: bool,
+    // `c`: preview only the changed lines
+    changes_only: bool,
+    jump_format: JumpFormat,
+    // For `--summary`: when the review started, and the hunks shown in the preview since
+    started: Instant,
+    reviewed: BTreeSet<usize>,
+    // Change block the preview is scrolled to, as (hunk, body line where the block starts)
+    focus: Option<(usize, usize)>,
+    // `--max-line-len`, and the hunk whose lines are shown in full regardless
+    max_line_len: Option<usize>,
+    full_lines: Option<usize>,
+    sources: Sources,
+    output_context: Option<usize>,
     status: String,
     list_state: ListState,
+    keymap: Keymap,
+    // Keys typed so far of a multi-key binding such as `gg`
+    pending_keys: Vec<Chord>,
+    show_help: bool,
+    // Text typed after `:`, while the command line is open
+    command: Option<String>,
+    picker: Option<FilePicker>,
+    budget: RenderBudget,
+    // `--auto-quit-after`: idle time after which to save and quit
+    auto_quit: Option<Duration>,
+    last_input: Instant,
+    // Unreliable hunks the last save held back, while `y` (confirm mode) would let them through
+    confirm_unreliable: Option<Vec<usize>>,
+    // Startup problems (parsing, key bindings), listed in the help overlay
+    warnings: Vec<String>,
 }
 
 impl App {
-    fn new(files: Vec<FileDiff>, hunks: Vec<Hunk>, input: PathBuf, output: PathBuf) -> Self {
+    fn new(
+        files: Vec<FileDiff>,
+        hunks: Vec<Hunk>,
+        input: PathBuf,
+        output: PathBuf,
+        keymap: Keymap,
+    ) -> Self {
         let order: Vec<usize> = (0..hunks.len()).collect();
+        let file_order: Vec<usize> = (0..files.len()).collect();
+        let counts = hunks.iter().map(|h| change_counts(&h.lines)).collect();
         let mut list_state = ListState::default();
         if !order.is_empty() {
             list_state.select(Some(0));
@@ -238,96 +1285,1110 @@ impl App {
         Self {
             files,
             hunks,
+            counts,
             order,
+            file_order,
+            view: View::Hunks,
             cursor: 0,
+            line_cursor: None,
             input_path: input,
             output_path: output,
+            repo_root: PathBuf::from("."),
+            check_tree: false,
+            tree_stamps: HashMap::new(),
+            apply_cached: false,
+            session: None,
+            selection_path: None,
+            report_path: None,
+            anonymizer: None,
+            split_dir: None,
+            split_written: BTreeSet::new(),
+            post_save: None,
+            whole_file: false,
+            show_headers: false,
+            block_view: false,
+            changes_only: false,
+            jump_format: JumpFormat::Colon,
+            started: Instant::now(),
+            reviewed: BTreeSet::new(),
+            focus: None,
+            max_line_len: None,
+            full_lines: None,
+            output_context: None,
+            sources: Sources::new(None, PathBuf::from(".")),
             status: "↑/↓ to move, Space to toggle & SAVE, q to quit".into(),
             list_state,
+            keymap,
+            pending_keys: Vec::new(),
+            show_help: false,
+            command: None,
+            picker: None,
+            budget: RenderBudget::new(),
+            auto_quit: None,
+            last_input: Instant::now(),
+            confirm_unreliable: None,
+            warnings: Vec::new(),
         }
     }
 
-    fn toggle_current_and_save(&mut self) -> Result<()> {
-        if let Some(&idx) = self.order.get(self.cursor) {
-            self.hunks[idx].marked = !self.hunks[idx].marked;
-        }
-        self.write_filtered_patch()
-            .context("writing filtered patch after Space")?;
-        let count = self.hunks.iter().filter(|h| h.marked).count();
-        self.status = format!(
-            "Saved {} selected hunk(s) → {}",
-            count,
-            self.output_path.display()
-        );
-        Ok(())
-    }
-    fn move_cursor(&mut self, dir: i32) {
-        if self.order.is_empty() {
-            self.cursor = 0;
-            self.list_state.select(None);
-            return;
-        }
-        let len = self.order.len() as i32;
-        let mut cur = self.cursor as i32 + dir;
-        if cur < 0 {
-            cur = 0;
-        }
-        if cur >= len {
-            cur = len - 1;
+    /// Mark the hunks listed in a selection manifest. Returns how many were marked and how
+    /// many of the manifest's ids match no hunk in the input.
+    fn load_selection(&mut self, path: &Path) -> Result<(usize, usize)> {
+        let mut wanted = session::load_selection(path)?;
+        let mut n = 0;
+        for h in &mut self.hunks {
+            if wanted.remove(&hunk_id(&self.files[h.file_idx], h)) {
+                h.marked = true;
+                n += 1;
+            }
         }
-        self.cursor = cur as usize;
-        self.list_state.select(Some(self.cursor));
+        Ok((n, wanted.len()))
     }
 
-    fn write_filtered_patch(&self) -> Result<()> {
-        // Group selected hunks by file
-        let mut out = String::new();
-        for (fidx, f) in self.files.iter().enumerate() {
-            let selected: Vec<&Hunk> = f
-                .hunks
-                .iter()
-                .map(|&hidx| &self.hunks[hidx])
-                .filter(|h| h.marked)
-                .collect();
-            if selected.is_empty() {
-                continue;
-            }
-            // Write headers exactly as in the input
-            for h in &f.headers {
-                out.push_str(h);
-                out.push('\n');
+    /// Execute a `:` command.
+    fn run_command(&mut self, line: &str) -> Result<()> {
+        let (name, arg) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+        match name {
+            "" => {}
+            "select-expr" => {
+                let expr = Expr::parse(arg.trim()).map_err(|e| anyhow!(e.short()))?;
+                let n = self.select_matching(&expr);
+                self.save()?;
+                self.status = format!("{n} hunk(s) matched • {}", self.status);
             }
-            // Write selected hunks for this file
-            for h in selected {
-                out.push_str(&h.header);
-                out.push('\n');
-                for l in &h.lines {
-                    out.push_str(l);
-                    out.push('\n');
+            "hot" => {
+                let (path, start, end) = parse_hot_range(arg.trim())
+                    .ok_or_else(|| anyhow!("usage: :hot PATH:START-END (new-file line numbers)"))?;
+                let mut n = 0;
+                for h in &mut self.hunks {
+                    if expr::glob_match(path, &self.files[h.file_idx].path())
+                        && new_side_overlaps(h, start, end)
+                    {
+                        h.marked = true;
+                        n += 1;
+                    }
                 }
+                self.save()?;
+                self.status = format!(
+                    "{n} hunk(s) touch {path} lines {start}-{end} • {}",
+                    self.status
+                );
+            }
+            _ => {
+                return Err(anyhow!(
+                    "unknown command :{name} (try :select-expr EXPR or :hot PATH:START-END)"
+                ));
             }
         }
-        fs::write(&self.output_path, out)?;
         Ok(())
     }
-}
 
-fn main() -> Result<()> {
-    let opts = Opts::parse();
-    let input_text = fs::read_to_string(&opts.input)
-        .with_context(|| format!("failed to read {}", opts.input.display()))?
-        .replace("\r\n", "\n")
-        .replace('\t', "    ");
-    let (files, hunks) = parse_unified_diff(&input_text)?;
-    if hunks.is_empty() {
-        return Err(anyhow!("No hunks found in {}", opts.input.display()));
+    /// Mark every hunk the expression matches. Returns how many matched.
+    fn select_matching(&mut self, expr: &Expr) -> usize {
+        let mut n = 0;
+        for h in &mut self.hunks {
+            if expr.matches(&self.files[h.file_idx], h) {
+                h.marked = true;
+                n += 1;
+            }
+        }
+        n
     }
 
-    // Prepare app
-    let mut app = App::new(files, hunks, opts.input, opts.output);
+    fn preview_toggles(&self) -> PreviewToggles {
+        PreviewToggles {
+            line_limit: self
+                .max_line_len
+                .filter(|_| self.full_lines != self.order.get(self.cursor).copied()),
+            // Line mode picks body lines in order, so it keeps every line on screen
+            changes_only: self.changes_only && self.line_cursor.is_none(),
+        }
+    }
 
-    // TUI setup
-    enable_raw_mode()?;
+    fn mode(&self) -> Mode {
+        if self.confirm_unreliable.is_some() {
+            Mode::Confirm
+        } else if self.line_cursor.is_some() {
+            Mode::Line
+        } else {
+            Mode::Normal
+        }
+    }
+
+    /// Run a bound action. Returns `Ok(true)` when the app should quit.
+    fn perform(&mut self, action: Action) -> Result<bool> {
+        match action {
+            // Quit. Nothing else to do; file has been kept updated on every Space.
+            Action::Quit => return Ok(true),
+            Action::Help => self.show_help = true,
+            Action::CommandLine => self.command = Some(String::new()),
+            Action::FilePicker => self.picker = Some(FilePicker::default()),
+            Action::MoveUp => self.move_cursor(-1),
+            Action::MoveDown => self.move_cursor(1),
+            Action::MoveTop => self.move_cursor(-(self.order.len() as i32)),
+            Action::MoveBottom => self.move_cursor(self.order.len() as i32),
+            Action::ToggleHunk => self.toggle_current_and_save()?,
+            Action::MarkRestOfFile => self.mark_rest_of_file()?,
+            Action::ClearFile => self.clear_file()?,
+            Action::ToggleFileView => self.toggle_file_view(),
+            Action::ApplyHunk => self.apply_current()?,
+            Action::WholeFile => self.whole_file = !self.whole_file,
+            Action::ShowHeaders => self.show_headers = !self.show_headers,
+            Action::BlockView => self.block_view = !self.block_view,
+            Action::ChangesOnly => {
+                self.changes_only = !self.changes_only;
+                self.status = if self.changes_only {
+                    "Preview shows changed lines only (c = show context again)".into()
+                } else {
+                    "Preview shows context again".into()
+                };
+            }
+            Action::NextChange => self.focus_next_change(),
+            Action::NextAddsHunk => self.jump_to_dominant(Dominant::Adds),
+            Action::NextDelsHunk => self.jump_to_dominant(Dominant::Dels),
+            Action::FullLines => {
+                let current = self.order.get(self.cursor).copied();
+                self.full_lines = if self.full_lines == current {
+                    None
+                } else {
+                    current
+                };
+            }
+            Action::CopyMarkdown => {
+                if let Some(&hidx) = self.order.get(self.cursor) {
+                    let hunk = &self.hunks[hidx];
+                    let file = &self.files[hunk.file_idx];
+                    copy_to_clipboard(&hunk_as_markdown(file, hunk))?;
+                    self.status =
+                        format!("Copied hunk of {} as a markdown diff block", file.path());
+                }
+            }
+            Action::CopyLocation => {
+                if let Some(&hidx) = self.order.get(self.cursor) {
+                    let hunk = &self.hunks[hidx];
+                    let file = &self.files[hunk.file_idx];
+                    // Where the hunk starts in the new file; a pure deletion names the line
+                    // before it, which may be 0
+                    let line =
+                        parse_hunk_header(&hunk.header).map_or(1, |(r, _)| r.new_start.max(1));
+                    let location = self.jump_format.format(&file.path(), line);
+                    copy_to_clipboard(&location)?;
+                    self.status = format!("Copied {location}");
+                }
+            }
+            Action::ToggleRenderBudget => {
+                self.budget.enabled = !self.budget.enabled;
+                self.status = if self.budget.enabled {
+                    "Render budget on: detail is reduced while frames are slow".into()
+                } else {
+                    "Full rendering: render budget off".into()
+                };
+            }
+            Action::LineMode | Action::LeaveLineMode => self.toggle_line_mode(),
+            Action::LineUp => self.move_line_cursor(-1),
+            Action::LineDown => self.move_line_cursor(1),
+            Action::ToggleLine => self.toggle_line_and_save()?,
+            Action::ConfirmUnreliable => {
+                for h in self.confirm_unreliable.take().unwrap_or_default() {
+                    self.hunks[h].unreliable_ok = true;
+                }
+                self.save()?;
+            }
+        }
+        Ok(false)
+    }
+
+    /// Feed one key press through the keymap. Returns `Ok(true)` when the app should quit.
+    fn handle_key(&mut self, chord: Chord) -> Result<bool> {
+        if self.show_help {
+            // Any key closes the help overlay
+            self.show_help = false;
+            return Ok(false);
+        }
+        if let Some(cmd) = &mut self.command {
+            match chord.code() {
+                KeyCode::Esc => self.command = None,
+                KeyCode::Enter => {
+                    let line = std::mem::take(cmd);
+                    self.command = None;
+                    self.run_command(&line)?;
+                }
+                // Backspace on an empty command line closes it
+                KeyCode::Backspace if cmd.pop().is_none() => self.command = None,
+                KeyCode::Char(c) => cmd.push(c),
+                _ => {}
+            }
+            return Ok(false);
+        }
+        if let Some(picker) = &mut self.picker {
+            let matches = picker.matches(picker_labels(&self.files));
+            match chord.code() {
+                KeyCode::Esc => self.picker = None,
+                KeyCode::Enter => {
+                    if let Some(m) = matches.get(picker.selected) {
+                        let file_idx = m.file_idx;
+                        self.jump_to_file(file_idx);
+                    }
+                    self.picker = None;
+                }
+                KeyCode::Up => picker.move_by(-1, matches.len()),
+                KeyCode::Down | KeyCode::Tab => picker.move_by(1, matches.len()),
+                KeyCode::Backspace => {
+                    picker.query.pop();
+                    picker.selected = 0;
+                }
+                KeyCode::Char(c) => {
+                    picker.query.push(c);
+                    picker.selected = 0;
+                }
+                _ => {}
+            }
+            return Ok(false);
+        }
+        self.pending_keys.push(chord);
+        let mut resolved = self.keymap.resolve(self.mode(), &self.pending_keys);
+        if matches!(resolved, Resolved::Unbound) && self.pending_keys.len() > 1 {
+            // An abandoned sequence: start over from the latest key
+            self.pending_keys = vec![chord];
+            resolved = self.keymap.resolve(self.mode(), &self.pending_keys);
+        }
+        match resolved {
+            Resolved::Pending => Ok(false),
+            // Any other key dismisses the prompt and does what it normally does
+            Resolved::Unbound if self.confirm_unreliable.take().is_some() => {
+                self.pending_keys.clear();
+                self.handle_key(chord)
+            }
+            Resolved::Unbound => {
+                self.pending_keys.clear();
+                Ok(false)
+            }
+            Resolved::Action(action) => {
+                self.pending_keys.clear();
+                self.perform(action)
+            }
+        }
+    }
+
+    fn toggle_current_and_save(&mut self) -> Result<()> {
+        if let Some(&idx) = self.order.get(self.cursor) {
+            match self.view {
+                View::Hunks => self.hunks[idx].marked = !self.hunks[idx].marked,
+                View::Files => {
+                    // Select the whole file unless it's already fully selected
+                    let file = &self.files[self.hunks[idx].file_idx];
+                    let mark = !file.hunks.iter().all(|&h| self.hunks[h].marked);
+                    for &h in &file.hunks {
+                        self.hunks[h].marked = mark;
+                    }
+                }
+            }
+        }
+        self.save()
+    }
+
+    /// Mark the current hunk and every later hunk of the same file, then save.
+    fn mark_rest_of_file(&mut self) -> Result<()> {
+        let Some(&idx) = self.order.get(self.cursor) else {
+            return Ok(());
+        };
+        let file = &self.files[self.hunks[idx].file_idx];
+        let from = file.hunks.iter().position(|&h| h == idx).unwrap_or(0);
+        let rest = &file.hunks[from..];
+        let newly = rest.iter().filter(|&&h| !self.hunks[h].marked).count();
+        for &h in rest {
+            self.hunks[h].marked = true;
+        }
+        let total = rest.len();
+        self.save()?;
+        self.status = format!(
+            "Marked {newly} hunk(s) to the end of the file ({total} from here) • {}",
+            self.status
+        );
+        Ok(())
+    }
+
+    /// Scroll the preview to the next run of `+`/`-` lines of the current hunk (in the file
+    /// view: of the file's hunks), wrapping around to the first one after the last.
+    fn focus_next_change(&mut self) {
+        let Some(&hidx) = self.order.get(self.cursor) else {
+            return;
+        };
+        let shown = match self.view {
+            View::Hunks => vec![hidx],
+            View::Files => self.files[self.hunks[hidx].file_idx].hunks.clone(),
+        };
+        let starts: Vec<(usize, usize)> = shown
+            .into_iter()
+            .flat_map(|h| {
+                let starts = blocks::change_starts(&self.hunks[h].lines);
+                starts.into_iter().map(move |line| (h, line))
+            })
+            .collect();
+        if starts.is_empty() {
+            return;
+        }
+        let next = self
+            .focus
+            .and_then(|focus| starts.iter().position(|&s| s == focus))
+            .map_or(0, |i| (i + 1) % starts.len());
+        self.focus = Some(starts[next]);
+        self.status = format!("Change {}/{}", next + 1, starts.len());
+    }
+
+    /// Move the cursor to the next row whose hunk is dominated by `kind`, wrapping around.
+    fn jump_to_dominant(&mut self, kind: Dominant) {
+        let len = self.order.len();
+        let next = (1..=len)
+            .map(|step| (self.cursor + step) % len)
+            .find(|&pos| Dominant::of(self.counts[self.order[pos]]) == Some(kind));
+        let Some(pos) = next else {
+            self.status = format!("No hunk is {}", kind.describe());
+            return;
+        };
+        self.cursor = pos;
+        self.list_state.select(Some(self.cursor));
+        let (adds, dels) = self.counts[self.order[pos]];
+        let total = self
+            .order
+            .iter()
+            .filter(|&&h| Dominant::of(self.counts[h]) == Some(kind))
+            .count();
+        self.status = format!(
+            "Hunk is {} (+{adds} -{dels}); {total} such hunk(s)",
+            kind.describe()
+        );
+    }
+
+    /// Put the cursor on the first hunk of a file (its row in the file view).
+    fn jump_to_file(&mut self, file_idx: usize) {
+        let Some(pos) = self
+            .order
+            .iter()
+            .position(|&h| self.hunks[h].file_idx == file_idx)
+        else {
+            self.status = format!(
+                "No hunk of {} is listed here",
+                self.files[file_idx].file_label
+            );
+            return;
+        };
+        self.cursor = pos;
+        self.list_state.select(Some(self.cursor));
+        self.status = format!("Jumped to {}", self.files[file_idx].file_label);
+    }
+
+    /// Unmark every hunk of the current file, leaving other files alone, then save.
+    fn clear_file(&mut self) -> Result<()> {
+        let Some(&idx) = self.order.get(self.cursor) else {
+            return Ok(());
+        };
+        let file = &self.files[self.hunks[idx].file_idx];
+        let mut cleared = 0;
+        for &h in &file.hunks {
+            cleared += usize::from(std::mem::take(&mut self.hunks[h].marked));
+        }
+        let label = file.file_label.clone();
+        self.save()?;
+        self.status = format!("Cleared {cleared} hunk(s) of {label} • {}", self.status);
+        Ok(())
+    }
+
+    /// Switch between one row per hunk and one row per file, keeping the cursor on the same file.
+    fn toggle_file_view(&mut self) {
+        let current = self.order.get(self.cursor).copied();
+        self.view = match self.view {
+            View::Hunks => View::Files,
+            View::Files => View::Hunks,
+        };
+        self.order = match self.view {
+            View::Hunks => (0..self.hunks.len()).collect(),
+            View::Files => self
+                .files
+                .iter()
+                .filter_map(|f| f.hunks.first().copied())
+                .collect(),
+        };
+        self.cursor = current
+            .and_then(|cur| {
+                let file_idx = self.hunks[cur].file_idx;
+                self.order.iter().position(|&h| match self.view {
+                    View::Hunks => h == cur,
+                    View::Files => self.hunks[h].file_idx == file_idx,
+                })
+            })
+            .unwrap_or(0);
+        self.list_state.select(Some(self.cursor));
+        self.status = match self.view {
+            View::Hunks => "Showing hunks".into(),
+            View::Files => format!(
+                "Showing {} file(s); Space toggles a whole file",
+                self.order.len()
+            ),
+        };
+    }
+
+    /// Send the current hunk (or, in the file view, the file's hunks) to `git apply` and record
+    /// the outcome. Hunks already applied are refused so they don't land twice.
+    fn apply_current(&mut self) -> Result<()> {
+        let Some(&hidx) = self.order.get(self.cursor) else {
+            return Ok(());
+        };
+        let targets: Vec<usize> = match self.view {
+            View::Hunks => vec![hidx],
+            View::Files => self.files[self.hunks[hidx].file_idx].hunks.clone(),
+        };
+        if targets.iter().all(|&h| self.hunks[h].apply.is_applied()) {
+            self.status = "Already applied; not applying again".into();
+            return Ok(());
+        }
+        let targets: Vec<usize> = targets
+            .into_iter()
+            .filter(|&h| !self.hunks[h].apply.is_applied())
+            .collect();
+        if targets.iter().any(|&h| !self.hunks[h].writable()) {
+            self.status =
+                "Not applying unreliable (truncated or wrapped) hunks; select them and confirm with y first"
+                    .into();
+            return Ok(());
+        }
+        // The targets all come from one file; render just them, each as it stands in the tree
+        let pending: Vec<Hunk> = targets
+            .iter()
+            .map(|&h| pending_hunk(&self.hunks[h]).into_owned())
+            .collect();
+        let mut file = self.files[self.hunks[targets[0]].file_idx].clone();
+        file.hunks = (0..pending.len()).collect();
+        let (patch, _) = render_patch(
+            std::slice::from_ref(&file),
+            &pending,
+            &[0],
+            |_| true,
+            false,
+            None,
+            None,
+        );
+        let result = git::apply(&self.repo_root, &patch, self.apply_cached);
+        // git numbers the hunks it got (all from one file) from 1, skipping ones left empty
+        let numbered: Vec<usize> = targets
+            .iter()
+            .zip(&pending)
+            .filter(|(_, p)| !p.header_only && effective_hunk(p, false).is_some())
+            .map(|(&h, _)| h)
+            .collect();
+        let offsets = match &result {
+            Ok(verbose) => git::hunk_offsets(verbose),
+            Err(_) => Vec::new(),
+        };
+        let mut partial = 0;
+        for &h in &targets {
+            let hunk = &mut self.hunks[h];
+            if result.is_err() {
+                hunk.apply = ApplyState::Failed;
+                continue;
+            }
+            // Lines left excluded stay out of the tree; the hunk counts as applied once none are
+            let sent: Vec<usize> = changed_lines(hunk)
+                .filter(|i| !hunk.excluded.contains(i))
+                .collect();
+            hunk.applied_lines.extend(sent);
+            if changed_lines(hunk).all(|i| hunk.applied_lines.contains(&i)) {
+                hunk.applied_lines.clear();
+                hunk.apply = ApplyState::Applied(0);
+            } else {
+                hunk.apply = ApplyState::NotApplied;
+                partial += 1;
+            }
+        }
+        for &(n, offset) in &offsets {
+            if let Some(&h) = n.checked_sub(1).and_then(|i| numbered.get(i))
+                && self.hunks[h].apply.is_applied()
+            {
+                self.hunks[h].apply = ApplyState::Applied(offset);
+            }
+        }
+        self.sync_session()?;
+        // The file's hunks changed state even when the tree didn't (`--apply-cached`)
+        self.tree_stamps.remove(&self.hunks[targets[0]].file_idx);
+        self.check_conflicts();
+        match result {
+            Ok(_) => {
+                let target = if self.apply_cached {
+                    "index"
+                } else {
+                    "working tree"
+                };
+                self.status = format!("Applied {} hunk(s) to the {target}", targets.len());
+                if partial > 0 {
+                    self.status += &format!(
+                        " ({partial} only in part: include the rest of their lines and apply again)"
+                    );
+                }
+                if !offsets.is_empty() {
+                    let shifts: Vec<String> = offsets
+                        .iter()
+                        .map(|(_, offset)| format!("{offset:+}"))
+                        .collect();
+                    self.status += &format!(
+                        "; {} at an offset ({} line(s)): the {target} had drifted",
+                        offsets.len(),
+                        shifts.join(", ")
+                    );
+                }
+            }
+            Err(e) => self.status = format!("ERROR: {e:#}"),
+        }
+        Ok(())
+    }
+
+    /// Flag the hunks whose old side (context and `-` lines) can't be found in the working tree,
+    /// anywhere in the file, since `git apply` would reject or fuzz them. Hunks already applied
+    /// from here and files that don't exist yet are left alone. Files unchanged since the last
+    /// check keep their flags.
+    fn check_conflicts(&mut self) {
+        if !self.check_tree {
+            return;
+        }
+        for (fidx, file) in self.files.iter().enumerate() {
+            let stamp = source::tree_stamp(&self.repo_root, file);
+            if self.tree_stamps.insert(fidx, stamp) == Some(stamp) {
+                continue;
+            }
+            let tree = source::working_tree(&self.repo_root, file);
+            for &h in &file.hunks {
+                let hunk = &mut self.hunks[h];
+                hunk.conflict = match &tree {
+                    _ if hunk.header_only || hunk.apply.is_applied() => false,
+                    Some(lines) => {
+                        let near = parse_hunk_header(&hunk.header)
+                            .map_or(0, |(r, _)| r.old_start.saturating_sub(1));
+                        let pending = pending_hunk(hunk);
+                        let old = source::old_side(&pending.lines);
+                        source::locate(lines, &old, near).is_none()
+                    }
+                    // A file the patch modifies is gone
+                    None => file.old_path().is_some() && file.old_blob().is_some(),
+                };
+            }
+        }
+    }
+
+    /// Copy marks and apply results into the session file, when there is one.
+    fn sync_session(&mut self) -> Result<()> {
+        let Some(session) = &mut self.session else {
+            return Ok(());
+        };
+        for h in &self.hunks {
+            let id = hunk_id(&self.files[h.file_idx], h);
+            if h.marked {
+                session.marked.insert(id.clone());
+            } else {
+                session.marked.remove(&id);
+            }
+            if h.applied_lines.is_empty() {
+                session.partial.remove(&id);
+            } else {
+                session.partial.insert(id.clone(), h.applied_lines.clone());
+            }
+            if h.apply != ApplyState::NotApplied {
+                session.applied.insert(id, h.apply);
+            }
+        }
+        session.save()
+    }
+
+    /// Restore marks and apply results recorded in the session file.
+    fn restore_session(&mut self) {
+        let Some(session) = &self.session else {
+            return;
+        };
+        for h in &mut self.hunks {
+            let id = hunk_id(&self.files[h.file_idx], h);
+            h.marked |= session.marked.contains(&id);
+            if let Some(&state) = session.applied.get(&id) {
+                h.apply = state;
+            }
+            if let Some(lines) = session.partial.get(&id) {
+                h.applied_lines = lines.clone();
+            }
+        }
+    }
+
+    fn save(&mut self) -> Result<()> {
+        self.check_conflicts();
+        let report = self
+            .write_filtered_patch()
+            .context("writing filtered patch after Space")?;
+        self.sync_session()?;
+        let count = self.hunks.iter().filter(|h| h.marked).count();
+        if let Some(hook) = &mut self.post_save {
+            hook.start(&self.output_path, count - report.unreliable_held)?;
+        }
+        self.status = format!(
+            "Saved {} selected hunk(s) → {}",
+            count,
+            self.output_path.display()
+        );
+        if let Some(dir) = &self.split_dir {
+            self.status.push_str(&format!(
+                " (split into {} patch(es) in {})",
+                self.split_written.len(),
+                dir.display()
+            ));
+        }
+        for note in report.notes() {
+            self.status.push_str(&format!(" • {note}"));
+        }
+        if report.unreliable_held > 0 {
+            self.status.push_str(": press y to write them anyway");
+            let held = self.hunks.iter().enumerate();
+            self.confirm_unreliable = Some(
+                held.filter(|(_, h)| h.marked && !h.writable())
+                    .map(|(i, _)| i)
+                    .collect(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Report a failed `--post-save-cmd` once it has finished.
+    fn poll_post_save(&mut self) {
+        let Some(hook) = &mut self.post_save else {
+            return;
+        };
+        match hook.poll() {
+            Ok(None) => {}
+            Ok(Some(failure)) => self.status = format!("ERROR: {failure}"),
+            Err(e) => self.status = format!("ERROR: {e:#}"),
+        }
+    }
+
+    /// Count the hunks now in the preview as reviewed: the current one, or in the file view all
+    /// of the current file's.
+    fn note_reviewed(&mut self) {
+        let Some(&hidx) = self.order.get(self.cursor) else {
+            return;
+        };
+        match self.view {
+            View::Hunks => {
+                self.reviewed.insert(hidx);
+            }
+            View::Files => self
+                .reviewed
+                .extend(&self.files[self.hunks[hidx].file_idx].hunks),
+        }
+    }
+
+    /// The `--summary` line: `Reviewed 87 of 120 hunk(s), selected 23, in 4m12s`.
+    fn summary(&self) -> String {
+        let secs = self.started.elapsed().as_secs();
+        let took = match (secs / 3600, secs / 60 % 60, secs % 60) {
+            (0, 0, s) => format!("{s}s"),
+            (0, m, s) => format!("{m}m{s:02}s"),
+            (h, m, s) => format!("{h}h{m:02}m{s:02}s"),
+        };
+        format!(
+            "Reviewed {} of {} hunk(s), selected {}, in {took}",
+            self.reviewed.len(),
+            self.hunks.len(),
+            self.hunks.iter().filter(|h| h.marked).count()
+        )
+    }
+
+    /// Time left before `--auto-quit-after` saves and quits, if it's set.
+    fn auto_quit_in(&self) -> Option<Duration> {
+        self.auto_quit
+            .map(|limit| limit.saturating_sub(self.last_input.elapsed()))
+    }
+
+    /// Indices of the `+`/`-` lines of a hunk, the only ones line mode can exclude.
+    fn change_lines(&self, hidx: usize) -> Vec<usize> {
+        self.hunks[hidx]
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| l.starts_with('+') || l.starts_with('-'))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn toggle_line_mode(&mut self) {
+        if self.line_cursor.is_some() {
+            self.line_cursor = None;
+            self.status = "Back to hunk selection".into();
+            return;
+        }
+        let Some(&hidx) = self.order.get(self.cursor) else {
+            return;
+        };
+        if self.view == View::Files {
+            self.status = "Line picking works on hunks; switch back to the hunk view first".into();
+            return;
+        }
+        self.line_cursor = self.change_lines(hidx).first().copied();
+        if self.line_cursor.is_some() {
+            self.status =
+                "Line mode: j/k = move • Space = include/exclude line & save • v/Esc = back".into();
+        }
+    }
+
+    fn move_line_cursor(&mut self, dir: i32) {
+        let (Some(&hidx), Some(cur)) = (self.order.get(self.cursor), self.line_cursor) else {
+            return;
+        };
+        let changes = self.change_lines(hidx);
+        let pos = changes.iter().position(|&i| i == cur).unwrap_or(0) as i32;
+        let next = (pos + dir).clamp(0, changes.len() as i32 - 1);
+        self.line_cursor = Some(changes[next as usize]);
+    }
+
+    fn toggle_line_and_save(&mut self) -> Result<()> {
+        let (Some(&hidx), Some(line)) = (self.order.get(self.cursor), self.line_cursor) else {
+            return Ok(());
+        };
+        let excluded = &mut self.hunks[hidx].excluded;
+        if !excluded.remove(&line) {
+            excluded.insert(line);
+        }
+        self.save()
+    }
+    fn move_cursor(&mut self, dir: i32) {
+        if self.order.is_empty() {
+            self.cursor = 0;
+            self.list_state.select(None);
+            return;
+        }
+        let len = self.order.len() as i32;
+        let mut cur = self.cursor as i32 + dir;
+        if cur < 0 {
+            cur = 0;
+        }
+        if cur >= len {
+            cur = len - 1;
+        }
+        self.cursor = cur as usize;
+        self.list_state.select(Some(self.cursor));
+    }
+
+    fn write_filtered_patch(&mut self) -> Result<WriteReport> {
+        let regen = match self.output_context {
+            Some(context) => {
+                let wanted: BTreeSet<usize> = self
+                    .hunks
+                    .iter()
+                    .filter(|h| h.marked)
+                    .map(|h| h.file_idx)
+                    .collect();
+                for &fidx in &wanted {
+                    self.sources.original(fidx, &self.files[fidx]);
+                }
+                let originals = wanted
+                    .into_iter()
+                    .filter_map(|fidx| Some((fidx, self.sources.cached(fidx)?.lines.as_slice())))
+                    .collect();
+                Some(Regen { context, originals })
+            }
+            None => None,
+        };
+        let include = |h: &Hunk| h.marked && h.writable();
+        let (out, mut report) = render_patch(
+            &self.files,
+            &self.hunks,
+            &self.file_order,
+            include,
+            false,
+            regen.as_ref(),
+            self.anonymizer.as_ref(),
+        );
+        report.conflicts = self
+            .hunks
+            .iter()
+            .filter(|h| h.marked && h.conflict && h.writable())
+            .count();
+        report.unreliable_held = self
+            .hunks
+            .iter()
+            .filter(|h| h.marked && !h.writable())
+            .count();
+        if let Some(path) = &self.report_path {
+            let text = report::render(
+                &self.files,
+                &self.hunks,
+                &self.file_order,
+                include,
+                &report.written,
+                self.anonymizer.as_ref(),
+                &out,
+            );
+            fs::write(path, text).with_context(|| format!("failed to write {}", path.display()))?;
+        }
+        fs::write(&self.output_path, out)?;
+        if let Some(path) = &self.selection_path {
+            let marked: Vec<(String, String)> = self
+                .hunks
+                .iter()
+                .filter(|h| h.marked)
+                .map(|h| {
+                    let file = &self.files[h.file_idx];
+                    (hunk_id(file, h), format!("{} {}", file.path(), h.header))
+                })
+                .collect();
+            session::save_selection(path, &marked)?;
+        }
+        if let Some(dir) = &self.split_dir {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+            let dirs: BTreeSet<String> = self
+                .hunks
+                .iter()
+                .filter(|h| include(h))
+                .map(|h| top_dir(&self.files[h.file_idx], self.anonymizer.as_ref()))
+                .collect();
+            for name in &dirs {
+                let (out, _) = render_patch(
+                    &self.files,
+                    &self.hunks,
+                    &self.file_order,
+                    |h| {
+                        include(h)
+                            && top_dir(&self.files[h.file_idx], self.anonymizer.as_ref()) == *name
+                    },
+                    false,
+                    regen.as_ref(),
+                    self.anonymizer.as_ref(),
+                );
+                let path = dir.join(format!("{name}.patch"));
+                fs::write(&path, out)
+                    .with_context(|| format!("failed to write {}", path.display()))?;
+            }
+            // Directories no longer selected: drop the patches written for them earlier
+            for stale in self.split_written.difference(&dirs) {
+                let path = dir.join(format!("{stale}.patch"));
+                if !path.exists() {
+                    continue;
+                }
+                fs::remove_file(&path)
+                    .with_context(|| format!("failed to remove {}", path.display()))?;
+            }
+            let manifest: String = dirs.iter().map(|name| format!("{name}\n")).collect();
+            let path = dir.join(SPLIT_MANIFEST);
+            fs::write(&path, manifest)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            self.split_written = dirs;
+        }
+        Ok(report)
+    }
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::parse();
+    let (input, input_text) = if let Some([a, b]) = opts.dir_diff.as_deref() {
+        (
+            PathBuf::from(format!("{}..{}", a.display(), b.display())),
+            dirdiff::diff_dirs(a, b, &opts.exclude)?,
+        )
+    } else {
+        match (&opts.input, opts.from_stash) {
+            (_, Some(n)) => {
+                let root = opts.repo_root.as_deref().unwrap_or(Path::new("."));
+                (
+                    PathBuf::from(format!("stash@{{{n}}}")),
+                    git::stash_patch(root, n)?,
+                )
+            }
+            (Some(path), None) => (
+                path.clone(),
+                fs::read_to_string(path)
+                    .with_context(|| format!("failed to read {}", path.display()))?,
+            ),
+            (None, None) => unreachable!("clap requires an input"),
+        }
+    };
+    let (mut files, mut hunks) = parse_unified_diff(&input_text)?;
+    if hunks.is_empty() {
+        return Err(anyhow!("No hunks found in {}", input.display()));
+    }
+
+    let mut warnings: Vec<String> = Vec::new();
+    if opts.lenient {
+        warnings.extend(lenient::repair(&files, &mut hunks).1);
+    } else if !opts.reflow {
+        // Miscounted headers are what `--reflow` is for, so only hint at `--lenient` without it
+        let (damaged, _) = lenient::repair(&files, &mut hunks.clone());
+        if damaged > 0 {
+            warnings.push(format!(
+                "{damaged} hunk(s) look truncated or mangled; --lenient repairs what it can"
+            ));
+        }
+    }
+    let dupes = find_duplicate_files(&files);
+    if !dupes.is_empty() {
+        let names = |skip: &[String]| -> String {
+            dupes
+                .iter()
+                .filter(|(label, _)| !skip.contains(label))
+                .map(|(label, idxs)| format!("{label} (×{})", idxs.len()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        if opts.merge_dupes {
+            let overlapping = merge_duplicate_files(&mut files, &mut hunks, &dupes);
+            if overlapping.len() < dupes.len() {
+                warnings.push(format!(
+                    "Merged duplicate file entries: {}",
+                    names(&overlapping)
+                ));
+            }
+            if !overlapping.is_empty() {
+                warnings.push(format!(
+                    "Duplicate file entries not merged, their hunks overlap: {}",
+                    overlapping.join(", ")
+                ));
+            }
+        } else {
+            warnings.push(format!(
+                "Duplicate file entries: {} (use --merge-dupes to combine them)",
+                names(&[])
+            ));
+        }
+    }
+
+    let file_order = match &opts.order_file {
+        Some(path) => file_output_order(&files, &read_order_file(path)?),
+        None => (0..files.len()).collect(),
+    };
+
+    let anonymizer = opts.anonymize.then(|| Anonymizer::new(&files));
+    if let (Some(anon), Some(path)) = (&anonymizer, &opts.anonymize_map) {
+        anon.write_map(path)?;
+    }
+
+    if opts.reflow {
+        for w in &warnings {
+            eprintln!("warning: {w}");
+        }
+        let fixed = reflow_patch(
+            &files,
+            &hunks,
+            &file_order,
+            &opts.output,
+            anonymizer.as_ref(),
+        )?;
+        eprintln!(
+            "Reflowed {} hunk(s), {} header(s) corrected → {}",
+            hunks.iter().filter(|h| !h.header_only).count(),
+            fixed,
+            opts.output.display()
+        );
+        return Ok(());
+    }
+
+    let keymap = Keymap::new(&opts.bindings)?;
+    warnings.extend(
+        keymap
+            .check(opts.strict_keys)?
+            .into_iter()
+            .map(|p| format!("key binding: {p}")),
+    );
+
+    // Prepare app
+    let mut app = App::new(files, hunks, input, opts.output, keymap);
+    app.file_order = file_order;
+    app.check_tree = opts.repo_root.is_some();
+    app.repo_root = opts.repo_root.unwrap_or_else(|| PathBuf::from("."));
+    // A directory diff's originals are right there in its first directory
+    let orig_dir = opts
+        .orig_dir
+        .or_else(|| opts.dir_diff.as_ref().map(|dirs| dirs[0].clone()));
+    app.sources = Sources::new(orig_dir, app.repo_root.clone());
+    app.output_context = opts.output_context;
+    app.auto_quit = opts.auto_quit_after.map(Duration::from_secs);
+    app.max_line_len = (opts.max_line_len > 0).then_some(opts.max_line_len);
+    app.jump_format = opts.jump_format;
+    app.apply_cached = opts.apply_cached;
+    if let Some(path) = opts.session {
+        app.session = Some(Session::load(path)?);
+        app.restore_session();
+    }
+    app.check_conflicts();
+    app.selection_path = opts.save_selection;
+    app.report_path = opts.report;
+    app.anonymizer = anonymizer;
+    if let Some(dir) = &opts.split_by_dir {
+        // Placeholders never clash with `root`
+        if app.anonymizer.is_none() {
+            check_split_names(&app.files)?;
+        }
+        app.split_written = read_split_manifest(dir);
+    }
+    app.split_dir = opts.split_by_dir;
+    let mut preselected = None;
+    if let Some(src) = &opts.expr {
+        let expr = Expr::parse(src).map_err(|e| anyhow!("--expr: {e}"))?;
+        preselected = Some((app.select_matching(&expr), "matching --expr"));
+    }
+    if opts.preselect == Some(Preselect::All) {
+        let mut n = 0;
+        for h in app.hunks.iter_mut().filter(|h| h.damage.is_none()) {
+            h.marked = true;
+            n += 1;
+        }
+        preselected = Some((n, "with --preselect"));
+    }
+    if let Some(path) = &opts.load_selection {
+        let (n, missing) = app.load_selection(path)?;
+        if missing > 0 {
+            warnings.push(format!(
+                "{missing} hunk(s) of {} aren't in the input",
+                path.display()
+            ));
+        }
+        preselected = Some((n, "from --load-selection"));
+    }
+
+    if opts.batch {
+        for w in &warnings {
+            eprintln!("warning: {w}");
+        }
+        let report = app.write_filtered_patch()?;
+        for note in report.notes() {
+            eprintln!("warning: {note}");
+        }
+        let written = app.hunks.iter().filter(|h| h.marked).count() - report.unreliable_held;
+        eprintln!(
+            "Wrote {} selected hunk(s) → {}",
+            written,
+            app.output_path.display()
+        );
+        if let Some(dir) = &app.split_dir {
+            eprintln!(
+                "Split into {} patch(es) in {}",
+                app.split_written.len(),
+                dir.display()
+            );
+        }
+        if let Some(cmd) = opts.post_save_cmd {
+            let hook = PostSave::new(cmd, app.input_path.clone());
+            if let Some(failure) = hook.run(&app.output_path, written)? {
+                return Err(anyhow!(failure));
+            }
+        }
+        return Ok(());
+    }
+
+    app.post_save = opts
+        .post_save_cmd
+        .map(|cmd| PostSave::new(cmd, app.input_path.clone()));
+    app.warnings = warnings.clone();
+    if let Some((n, source)) = preselected {
+        app.status = format!("Preselected {n} hunk(s) {source} (not saved yet; Space saves)");
+    }
+    if !warnings.is_empty() {
+        app.status = format!("WARNING: {}", warnings.join(" • "));
+    }
+
+    // TUI setup
+    enable_raw_mode()?;
     let mut stdout = io::stdout();
     execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
     let backend = CrosstermBackend::new(stdout);
@@ -344,6 +2405,9 @@ fn main() -> Result<()> {
     )?;
     terminal.show_cursor()?;
 
+    if opts.summary {
+        eprintln!("{}", app.summary());
+    }
     if let Err(e) = res {
         eprintln!("error: {e:?}");
         std::process::exit(1);
@@ -353,6 +2417,15 @@ fn main() -> Result<()> {
 
 fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<()> {
     loop {
+        app.poll_post_save();
+        app.note_reviewed();
+        if app.auto_quit_in() == Some(Duration::ZERO) {
+            app.save()?;
+            return Ok(());
+        }
+        let features = app.budget.features();
+        let toggles = app.preview_toggles();
+        let frame_start = Instant::now();
         terminal.draw(|f| {
             let area = f.area();
             // Vertical: [main, status]
@@ -373,16 +2446,57 @@ fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, app: &mut A
                 .enumerate()
                 .map(|(i, &hidx)| {
                     let h = &app.hunks[hidx];
-                    let prefix = if h.marked { "[x]" } else { "[ ]" };
-                    let line = Line::from(vec![
-                        Span::raw(format!("{prefix} ")),
-                        Span::styled(
-                            &app.files[h.file_idx].file_label,
-                            Style::default().add_modifier(Modifier::BOLD),
-                        ),
-                        Span::raw("  "),
-                        Span::raw(&h.display),
-                    ]);
+                    let line = match app.view {
+                        View::Hunks => {
+                            let prefix = match (h.marked, h.excluded.is_empty()) {
+                                (false, _) => "[ ]",
+                                (true, true) => "[x]",
+                                (true, false) => "[~]",
+                            };
+                            if features.compact {
+                                return ListItem::new(format!("{prefix} {}", h.display));
+                            }
+                            Line::from(vec![
+                                Span::raw(format!("{prefix} ")),
+                                apply_badge(h),
+                                damage_badge(h.damage),
+                                conflict_badge(h.conflict),
+                                Span::styled(
+                                    &app.files[h.file_idx].file_label,
+                                    Style::default().add_modifier(Modifier::BOLD),
+                                ),
+                                Span::raw("  "),
+                                Span::raw(&h.display),
+                            ])
+                        }
+                        View::Files => {
+                            let file = &app.files[h.file_idx];
+                            let marked = file.hunks.iter().filter(|&&h| app.hunks[h].marked).count();
+                            let prefix = match marked {
+                                0 => "[ ]",
+                                m if m == file.hunks.len() => "[x]",
+                                _ => "[~]",
+                            };
+                            if features.compact {
+                                return ListItem::new(format!("{prefix} {}", file.file_label));
+                            }
+                            let (adds, dels) = file.hunks.iter().fold((0, 0), |(a, d), &h| {
+                                let (ha, hd) = change_counts(&app.hunks[h].lines);
+                                (a + ha, d + hd)
+                            });
+                            Line::from(vec![
+                                Span::raw(format!("{prefix} ")),
+                                Span::styled(
+                                    &file.file_label,
+                                    Style::default().add_modifier(Modifier::BOLD),
+                                ),
+                                Span::raw(format!("  {marked}/{} hunk(s)  ", file.hunks.len())),
+                                Span::styled(format!("+{adds}"), Style::default().fg(Color::Green)),
+                                Span::raw(" "),
+                                Span::styled(format!("-{dels}"), Style::default().fg(Color::Red)),
+                            ])
+                        }
+                    };
                     let mut item = ListItem::new(line);
                     if i == app.cursor {
                         item = item.style(Style::default().add_modifier(Modifier::REVERSED));
@@ -391,78 +2505,1559 @@ fn run_app<B: ratatui::backend::Backend>(terminal: &mut Terminal<B>, app: &mut A
                 })
                 .collect();
 
+            let title = match app.view {
+                View::Hunks => "Hunks",
+                View::Files => "Files",
+            };
             let list =
-                List::new(items).block(Block::default().title("Hunks").borders(Borders::ALL));
+                List::new(items).block(Block::default().title(title).borders(Borders::ALL));
 
             f.render_stateful_widget(list, h[0], &mut app.list_state);
+            if features.scrollbar && app.order.len() > h[0].height.saturating_sub(2) as usize {
+                let mut scroll = ScrollbarState::new(app.order.len()).position(app.cursor);
+                f.render_stateful_widget(
+                    Scrollbar::new(ScrollbarOrientation::VerticalRight),
+                    h[0],
+                    &mut scroll,
+                );
+            }
 
             // === Right-hand PREVIEW ===
             let mut preview_lines: Vec<Line> = Vec::new();
+            let mut preview_title = String::from("Preview");
+            let mut preview_scroll = 0;
             if let Some(&hidx) = app.order.get(app.cursor) {
-                let hunk = &app.hunks[hidx];
-                // Header line
-                preview_lines.push(Line::from(Span::styled(
-                    hunk.header.clone(),
-                    Style::default().add_modifier(Modifier::BOLD),
-                )));
-                // Body lines with colorization by first char
-                for l in &hunk.lines {
-                    let (style, text) = match l.chars().next() {
-                        Some('+') => (Style::default().fg(Color::Green), l.clone()),
-                        Some('-') => (Style::default().fg(Color::Red), l.clone()),
-                        Some(' ') => (Style::default(), l.clone()),
-                        Some('\\') => (Style::default().fg(Color::Gray), l.clone()),
-                        _ => (Style::default(), l.clone()),
+                // Header-only entries show their headers anyway
+                if app.show_headers && !app.hunks[hidx].header_only {
+                    let file = &app.files[app.hunks[hidx].file_idx];
+                    preview_lines.extend(file.headers.iter().map(|l| {
+                        Line::from(Span::styled(l.as_str(), Style::default().fg(Color::Cyan)))
+                    }));
+                }
+                let header_rows = preview_lines.len();
+                // Preview line of each shown hunk's `@@` line, and whether bodies are in block
+                // layout
+                let mut hunk_rows: Vec<(usize, usize)> = Vec::new();
+                let mut in_blocks = false;
+                match app.view {
+                    View::Hunks
+                        if app.whole_file
+                            && !toggles.changes_only
+                            && !app.hunks[hidx].header_only =>
+                    {
+                        let hunk = &app.hunks[hidx];
+                        let file = &app.files[hunk.file_idx];
+                        match app.sources.original(hunk.file_idx, file) {
+                            Some(original) => {
+                                preview_title =
+                                    format!("Preview: whole file ({})", original.origin.describe());
+                                let row = header_rows
+                                    + push_whole_file_preview(
+                                        &mut preview_lines,
+                                        &original.lines,
+                                        file,
+                                        hunk,
+                                        app.line_cursor,
+                                        features,
+                                        toggles,
+                                    );
+                                // Leave a few lines of context above the hunk on screen
+                                preview_scroll = row.saturating_sub(3);
+                                hunk_rows.push((hidx, row));
+                            }
+                            None => {
+                                preview_title = "Preview: original not found, hunk only".into();
+                                hunk_rows.push((hidx, header_rows));
+                                push_hunk_preview(
+                                    &mut preview_lines,
+                                    file,
+                                    hunk,
+                                    app.line_cursor,
+                                    features,
+                                    toggles,
+                                );
+                            }
+                        }
+                    }
+                    // Line mode picks body lines in order, so it keeps the flat view
+                    View::Hunks
+                        if app.block_view
+                            && !toggles.changes_only
+                            && app.line_cursor.is_none() =>
+                    {
+                        let hunk = &app.hunks[hidx];
+                        hunk_rows.push((hidx, header_rows));
+                        in_blocks = true;
+                        push_block_preview(
+                            &mut preview_lines,
+                            &app.files[hunk.file_idx],
+                            hunk,
+                            features,
+                            toggles,
+                        );
+                    }
+                    View::Hunks => {
+                        hunk_rows.push((hidx, header_rows));
+                        push_hunk_preview(
+                            &mut preview_lines,
+                            &app.files[app.hunks[hidx].file_idx],
+                            &app.hunks[hidx],
+                            app.line_cursor,
+                            features,
+                            toggles,
+                        );
+                    }
+                    View::Files => {
+                        let file = &app.files[app.hunks[hidx].file_idx];
+                        in_blocks = app.block_view && !toggles.changes_only;
+                        for &h in &file.hunks {
+                            hunk_rows.push((h, preview_lines.len()));
+                            if in_blocks {
+                                push_block_preview(
+                                    &mut preview_lines,
+                                    file,
+                                    &app.hunks[h],
+                                    features,
+                                    toggles,
+                                );
+                            } else {
+                                push_hunk_preview(
+                                    &mut preview_lines,
+                                    file,
+                                    &app.hunks[h],
+                                    None,
+                                    features,
+                                    toggles,
+                                );
+                            }
+                        }
+                    }
+                }
+                if let Some((focus_hunk, line)) = app.focus
+                    && let Some(&(_, row)) = hunk_rows.iter().find(|(h, _)| *h == focus_hunk)
+                {
+                    let hunk = &app.hunks[focus_hunk];
+                    let layout = if in_blocks {
+                        Some(blocks::rows(&hunk.lines))
+                    } else if toggles.changes_only {
+                        Some(blocks::compact_rows(&hunk.lines))
+                    } else {
+                        None
                     };
-                    preview_lines.push(Line::from(Span::styled(text, style)));
+                    let offset = if let Some(layout) = layout {
+                        // Rows before the block; a pair takes two lines
+                        layout
+                            .iter()
+                            .take_while(|r| match **r {
+                                blocks::Row::Line(i) | blocks::Row::Pair(i, _) => i < line,
+                                blocks::Row::Fold(_) => true,
+                            })
+                            .map(|r| if matches!(r, blocks::Row::Pair(..)) { 2 } else { 1 })
+                            .sum()
+                    } else {
+                        line
+                    };
+                    // The block's first line, with one line above it
+                    preview_scroll = row + offset;
                 }
             } else {
                 preview_lines.push(Line::from("No hunk selected"));
             }
 
             let preview_area = h[1];
+            // Scroll by screen rows, which long lines wrap into several of
+            let preview_scroll = wrapped_rows(
+                &preview_lines,
+                preview_scroll,
+                preview_area.width.saturating_sub(2),
+            );
 
             // Clear the preview area so old content disappears
             f.render_widget(Clear, preview_area);
 
             let preview = Paragraph::new(preview_lines)
                 .wrap(Wrap { trim: false })
-                .block(Block::default().title("Preview").borders(Borders::ALL));
+                .scroll((preview_scroll, 0))
+                .block(Block::default().title(preview_title).borders(Borders::ALL));
 
             f.render_widget(preview, preview_area);
 
+            let current = app.order.get(app.cursor).map(|&h| &app.hunks[h]);
+            let first_line = match &app.command {
+                Some(cmd) => Line::from(format!(":{cmd}█")),
+                None => Line::from(app.status.clone()),
+            };
             let help = Paragraph::new(vec![
-                Line::from(app.status.clone()),
-                Line::from("Keys: ↑/↓ or j/k = move • Space/Enter = toggle & save • q = quit"),
+                first_line,
+                Line::from(
+                    "Keys: ↑/↓ or j/k = move • Space/Enter = toggle & save • v = pick lines • ? = help • q = quit",
+                ),
             ])
-            .block(Block::default().borders(Borders::ALL).title("Status"));
+            .block(Block::default().borders(Borders::ALL).title(status_title(features, app.auto_quit_in(), &app.input_path, current)));
             f.render_widget(help, v[1]);
+
+            if let Some(picker) = &app.picker {
+                draw_picker(f, area, picker, &app.files);
+            }
+
+            if app.show_help {
+                let overlay = centered_rect(area, 70, 80);
+                f.render_widget(Clear, overlay);
+                f.render_widget(
+                    Paragraph::new(help_lines(&app.keymap, &app.warnings))
+                        .wrap(Wrap { trim: false })
+                        .block(
+                            Block::default()
+                                .title("Help (any key to close)")
+                                .borders(Borders::ALL),
+                        ),
+                    overlay,
+                );
+            }
         })?;
+        app.budget.record(frame_start.elapsed());
 
         // Input
-        if event::poll(std::time::Duration::from_millis(250))? {
-            if let Event::Key(key) = event::read()? {
-                // Only react to "press" (not repeats)
-                if key.kind != KeyEventKind::Press {
-                    continue;
+        if event::poll(std::time::Duration::from_millis(250))?
+            && let Event::Key(key) = event::read()?
+        {
+            // Only react to "press" (not repeats)
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            app.last_input = Instant::now();
+            match app.handle_key(Chord::from_event(key)) {
+                Ok(true) => return Ok(()),
+                Ok(false) => {}
+                Err(e) => app.status = format!("ERROR: {e:#}"),
+            }
+        }
+    }
+}
+
+/// Put text on the system clipboard through the terminal (OSC 52), which also works over SSH.
+fn copy_to_clipboard(text: &str) -> Result<()> {
+    execute!(io::stdout(), CopyToClipboard::to_clipboard_from(text))
+        .context("copying to the clipboard")
+}
+
+/// A hunk as a GitHub/GitLab markdown snippet: the file path, then a fenced `diff` block.
+fn hunk_as_markdown(file: &FileDiff, hunk: &Hunk) -> String {
+    let body: Vec<&str> = if hunk.header_only {
+        file.headers.iter().map(String::as_str).collect()
+    } else {
+        std::iter::once(hunk.header.as_str())
+            .chain(hunk.lines.iter().map(String::as_str))
+            .collect()
+    };
+    // The fence has to be longer than any run of backticks in the body, or it closes early
+    let longest_run = body
+        .iter()
+        .flat_map(|l| l.split(|c| c != '`'))
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    let fence = "`".repeat(longest_run.max(2) + 1);
+    let mut out = format!("`{}`\n\n{fence}diff\n", file.path());
+    for l in body {
+        out.push_str(l);
+        out.push('\n');
+    }
+    out.push_str(&fence);
+    out.push('\n');
+    out
+}
+
+fn apply_badge(h: &Hunk) -> Span<'static> {
+    if !h.applied_lines.is_empty() {
+        return Span::styled("◐partly applied ", Style::default().fg(Color::Yellow));
+    }
+    match h.apply {
+        ApplyState::NotApplied => Span::raw(""),
+        ApplyState::Applied(0) => Span::styled("✓applied ", Style::default().fg(Color::Green)),
+        ApplyState::Applied(offset) => Span::styled(
+            format!("✓applied@{offset:+} "),
+            Style::default().fg(Color::Yellow),
+        ),
+        ApplyState::Failed => Span::styled("✗failed ", Style::default().fg(Color::Red)),
+    }
+}
+
+fn conflict_badge(conflict: bool) -> Span<'static> {
+    if conflict {
+        Span::styled("⚡conflict ", Style::default().fg(Color::Red))
+    } else {
+        Span::raw("")
+    }
+}
+
+fn damage_badge(damage: Option<Damage>) -> Span<'static> {
+    match damage {
+        None => Span::raw(""),
+        Some(d) => Span::styled(
+            format!("⚠{} ", d.describe()),
+            Style::default().fg(Color::Yellow),
+        ),
+    }
+}
+
+fn status_title(
+    features: RenderFeatures,
+    auto_quit_in: Option<Duration>,
+    input: &Path,
+    hunk: Option<&Hunk>,
+) -> Line<'static> {
+    let mut spans = vec![Span::raw("Status ")];
+    if let Some(h) = hunk {
+        spans.push(Span::styled(
+            format!(
+                "· {} line {}, byte {} ",
+                input.display(),
+                h.line_offset + 1,
+                h.byte_offset
+            ),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    if let Some(left) = auto_quit_in {
+        spans.push(Span::styled(
+            format!("· saving and quitting in {}s ", left.as_secs_f32().ceil()),
+            Style::default().fg(Color::Yellow),
+        ));
+    }
+    if features.level > 0 {
+        spans.push(Span::styled(
+            format!("· reduced rendering ({}/4, R = full) ", features.level),
+            Style::default().fg(Color::DarkGray),
+        ));
+    }
+    Line::from(spans)
+}
+
+/// Header and colorized body of one hunk, with excluded lines struck through. Header-only
+/// entries show the file headers they would write instead.
+fn push_hunk_preview<'a>(
+    out: &mut Vec<Line<'a>>,
+    file: &'a FileDiff,
+    hunk: &'a Hunk,
+    line_cursor: Option<usize>,
+    features: RenderFeatures,
+    toggles: PreviewToggles,
+) {
+    if hunk.header_only {
+        out.push(Line::from(Span::styled(
+            hunk.display.as_str(),
+            Style::default().add_modifier(Modifier::BOLD),
+        )));
+        out.extend(file.headers.iter().map(|l| Line::from(l.as_str())));
+        return;
+    }
+    // Header line
+    out.push(Line::from(Span::styled(
+        hunk.header.as_str(),
+        Style::default().add_modifier(Modifier::BOLD),
+    )));
+    let limit = features.preview_limit.unwrap_or(usize::MAX);
+    let rows = if toggles.changes_only {
+        blocks::compact_rows(&hunk.lines)
+    } else {
+        (0..hunk.lines.len()).map(blocks::Row::Line).collect()
+    };
+    // Body lines with colorization by first char
+    for row in rows.iter().take(limit) {
+        let blocks::Row::Line(i) = *row else {
+            out.push(Line::from(Span::styled(
+                "⋯",
+                Style::default().fg(Color::DarkGray),
+            )));
+            continue;
+        };
+        let l = &hunk.lines[i];
+        let mut style = match l.chars().next() {
+            _ if !features.highlight => Style::default(),
+            Some('+') => Style::default().fg(Color::Green),
+            Some('-') => Style::default().fg(Color::Red),
+            Some('\\') => Style::default().fg(Color::Gray),
+            _ => Style::default(),
+        };
+        if hunk.excluded.contains(&i) {
+            style = Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::CROSSED_OUT);
+        }
+        if line_cursor == Some(i) {
+            style = style.add_modifier(Modifier::REVERSED);
+        }
+        out.push(Line::from(Span::styled(clip(l, toggles.line_limit), style)));
+    }
+    if rows.len() > limit {
+        out.push(Line::from(format!("… {} more line(s)", rows.len() - limit)));
+    }
+}
+
+/// The block view of a hunk (see [`blocks`]): each removed line directly above the added line
+/// that replaces it, with the words that changed between them highlighted.
+fn push_block_preview<'a>(
+    out: &mut Vec<Line<'a>>,
+    file: &'a FileDiff,
+    hunk: &'a Hunk,
+    features: RenderFeatures,
+    toggles: PreviewToggles,
+) {
+    if hunk.header_only {
+        push_hunk_preview(out, file, hunk, None, features, toggles);
+        return;
+    }
+    out.push(Line::from(Span::styled(
+        hunk.header.as_str(),
+        Style::default().add_modifier(Modifier::BOLD),
+    )));
+    let style_of = |i: usize| {
+        let l = &hunk.lines[i];
+        if hunk.excluded.contains(&i) {
+            return Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::CROSSED_OUT);
+        }
+        match l.chars().next() {
+            _ if !features.highlight => Style::default(),
+            Some('+') => Style::default().fg(Color::Green),
+            Some('-') => Style::default().fg(Color::Red),
+            Some('\\') => Style::default().fg(Color::Gray),
+            _ => Style::default(),
+        }
+    };
+    let rows = blocks::rows(&hunk.lines);
+    let limit = features.preview_limit.unwrap_or(usize::MAX);
+    for row in rows.iter().take(limit) {
+        match *row {
+            blocks::Row::Line(i) => {
+                out.push(Line::from(Span::styled(
+                    clip(&hunk.lines[i], toggles.line_limit),
+                    style_of(i),
+                )));
+            }
+            // Too long to highlight word by word; show them clipped like any other line
+            blocks::Row::Pair(d, a)
+                if toggles.line_limit.is_some_and(|max| {
+                    hunk.lines[d].chars().count() > max || hunk.lines[a].chars().count() > max
+                }) =>
+            {
+                for i in [d, a] {
+                    out.push(Line::from(Span::styled(
+                        clip(&hunk.lines[i], toggles.line_limit),
+                        style_of(i),
+                    )));
                 }
-                match key.code {
-                    KeyCode::Char('q') => {
-                        // Quit. Nothing else to do; file has been kept updated on every Space.
-                        return Ok(());
-                    }
-                    KeyCode::Up => app.move_cursor(-1),
-                    KeyCode::Down => app.move_cursor(1),
-                    KeyCode::Char('k') => app.move_cursor(-1),
-                    KeyCode::Char('j') => app.move_cursor(1),
-                    KeyCode::Char(' ') | KeyCode::Enter => {
-                        if let Err(e) = app.toggle_current_and_save() {
-                            app.status = format!("ERROR: {e:#}");
-                        }
-                    }
-                    _ => {}
+            }
+            blocks::Row::Fold(n) => out.push(Line::from(Span::styled(
+                format!("┄ {n} unchanged line(s) ┄"),
+                Style::default().fg(Color::DarkGray),
+            ))),
+            blocks::Row::Pair(d, a) => {
+                let (old, new) = blocks::word_diff(&hunk.lines[d][1..], &hunk.lines[a][1..]);
+                for (i, parts) in [(d, old), (a, new)] {
+                    let base = style_of(i);
+                    let mut spans = vec![Span::styled(&hunk.lines[i][..1], base)];
+                    spans.extend(parts.into_iter().map(|(changed, text)| {
+                        let style = if changed && features.highlight && !hunk.excluded.contains(&i)
+                        {
+                            base.add_modifier(Modifier::REVERSED)
+                        } else {
+                            base
+                        };
+                        Span::styled(text, style)
+                    }));
+                    out.push(Line::from(spans));
                 }
             }
         }
     }
+    if rows.len() > limit {
+        out.push(Line::from(format!("… {} more row(s)", rows.len() - limit)));
+    }
+}
+
+/// The user's preview settings for one frame, drawn with whatever the render budget allows.
+#[derive(Debug, Clone, Copy)]
+struct PreviewToggles {
+    // Cap on characters shown per line (`--max-line-len`), unless `L` showed the hunk in full
+    line_limit: Option<usize>,
+    // `c`, outside line mode: only the changed lines
+    changes_only: bool,
+}
+
+/// A preview line cut to `limit` characters, with `…` and the number of characters left out.
+/// Only the display is affected; the hunk keeps the whole line.
+fn clip(line: &str, limit: Option<usize>) -> Cow<'_, str> {
+    match limit.and_then(|max| line.char_indices().nth(max)) {
+        Some((cut, _)) => Cow::Owned(format!(
+            "{}… (+{} chars)",
+            &line[..cut],
+            line[cut..].chars().count()
+        )),
+        None => Cow::Borrowed(line),
+    }
+}
+
+/// Lines of the original file kept around the hunk when the render budget limits previews.
+const WHOLE_FILE_CONTEXT_LIMIT: usize = 20;
+
+/// The hunk shown in place inside its original file, with the unchanged lines around it
+/// dimmed. Returns the index of the hunk's `@@` line among the lines pushed.
+fn push_whole_file_preview<'a>(
+    out: &mut Vec<Line<'a>>,
+    original: &'a [String],
+    file: &'a FileDiff,
+    hunk: &'a Hunk,
+    line_cursor: Option<usize>,
+    features: RenderFeatures,
+    toggles: PreviewToggles,
+) -> usize {
+    let Some((range, _)) = parse_hunk_header(&hunk.header) else {
+        push_hunk_preview(out, file, hunk, line_cursor, features, toggles);
+        return 0;
+    };
+    // A hunk that removes nothing sits *after* its start line
+    let before_end = if range.old_len == 0 {
+        range.old_start
+    } else {
+        range.old_start.saturating_sub(1)
+    }
+    .min(original.len());
+    let after_start = (before_end + range.old_len).min(original.len());
+    let (from, to) = match features.preview_limit {
+        Some(_) => (
+            before_end.saturating_sub(WHOLE_FILE_CONTEXT_LIMIT),
+            (after_start + WHOLE_FILE_CONTEXT_LIMIT).min(original.len()),
+        ),
+        None => (0, original.len()),
+    };
+    let dim = if features.highlight {
+        Style::default().fg(Color::DarkGray)
+    } else {
+        Style::default()
+    };
+    let context = |l: &'a String| {
+        Line::from(vec![
+            Span::styled(" ", dim),
+            Span::styled(clip(l, toggles.line_limit), dim),
+        ])
+    };
+    out.extend(original[from..before_end].iter().map(context));
+    push_hunk_preview(out, file, hunk, line_cursor, features, toggles);
+    out.extend(original[after_start..to].iter().map(context));
+    before_end - from
+}
+
+/// Screen rows the first `upto` of `lines` take in a wrapping preview `width` columns wide:
+/// where to scroll to bring line `upto` to the top.
+fn wrapped_rows(lines: &[Line], upto: usize, width: u16) -> u16 {
+    let before = lines[..upto.min(lines.len())].to_vec();
+    let rows = Paragraph::new(before)
+        .wrap(Wrap { trim: false })
+        .line_count(width);
+    u16::try_from(rows).unwrap_or(u16::MAX)
+}
+
+/// Files offered by the `F` picker: those with hunks of their own (duplicates folded by
+/// `--merge-dupes` have none left).
+fn picker_labels(files: &[FileDiff]) -> impl Iterator<Item = (usize, &str)> {
+    files
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| !f.hunks.is_empty())
+        .map(|(i, f)| (i, f.file_label.as_str()))
+}
+
+/// The `F` picker overlay: the query on top, then the matching files with matched characters
+/// highlighted.
+fn draw_picker(f: &mut ratatui::Frame, area: Rect, picker: &FilePicker, files: &[FileDiff]) {
+    let overlay = centered_rect(area, 60, 60);
+    f.render_widget(Clear, overlay);
+    let matches = picker.matches(picker_labels(files));
+    let block = Block::default()
+        .title(format!(
+            "Jump to file ({} match(es), Esc to close)",
+            matches.len()
+        ))
+        .borders(Borders::ALL);
+    let inner = block.inner(overlay);
+    f.render_widget(block, overlay);
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(1), Constraint::Min(0)])
+        .split(inner);
+    f.render_widget(Paragraph::new(format!("> {}█", picker.query)), rows[0]);
+    let hit = Style::default()
+        .fg(Color::Yellow)
+        .add_modifier(Modifier::BOLD);
+    let items: Vec<ListItem> = matches
+        .iter()
+        .map(|m| {
+            let spans: Vec<Span> = files[m.file_idx]
+                .file_label
+                .chars()
+                .enumerate()
+                .map(|(i, c)| {
+                    if m.positions.contains(&i) {
+                        Span::styled(c.to_string(), hit)
+                    } else {
+                        Span::raw(c.to_string())
+                    }
+                })
+                .collect();
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+    let mut state = ListState::default();
+    state.select((!matches.is_empty()).then_some(picker.selected));
+    f.render_stateful_widget(
+        List::new(items).highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+        rows[1],
+        &mut state,
+    );
+}
+
+fn centered_rect(area: Rect, pct_x: u16, pct_y: u16) -> Rect {
+    let w = area.width * pct_x / 100;
+    let h = area.height * pct_y / 100;
+    Rect::new(
+        area.x + (area.width - w) / 2,
+        area.y + (area.height - h) / 2,
+        w,
+        h,
+    )
+}
+
+/// Bindings grouped by mode, with overridden defaults struck through, then startup warnings.
+fn help_lines<'a>(keymap: &Keymap, warnings: &'a [String]) -> Vec<Line<'a>> {
+    let mut lines = Vec::new();
+    for mode in Mode::ALL {
+        lines.push(Line::from(Span::styled(
+            format!("{} mode", mode.name()),
+            Style::default().add_modifier(Modifier::BOLD),
+        )));
+        for b in keymap.bindings().iter().filter(|b| b.mode == mode) {
+            let keys = keys::format_keys(&b.keys);
+            let mut text = format!("  {keys:<12} {}", b.action.description());
+            let mut style = Style::default();
+            if b.overridden {
+                text.push_str("  (overridden)");
+                style = style
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::CROSSED_OUT);
+            } else if b.source == keys::Source::User {
+                text.push_str("  (custom)");
+                style = style.fg(Color::Cyan);
+            }
+            lines.push(Line::from(Span::styled(text, style)));
+        }
+        lines.push(Line::from(""));
+    }
+    if !warnings.is_empty() {
+        lines.push(Line::from(Span::styled(
+            "Warnings",
+            Style::default()
+                .fg(Color::Yellow)
+                .add_modifier(Modifier::BOLD),
+        )));
+        for w in warnings {
+            lines.push(Line::from(format!("  {w}")));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    /// A fresh directory under the system temp dir, unique to this test run.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("patchers-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Whether `git apply --check` accepts `patch` against a repo holding `files`.
+    fn git_apply_check(name: &str, files: &[(&str, &str)], patch: &str) -> bool {
+        let dir = scratch_dir(name);
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(args)
+                .current_dir(&dir)
+                .output()
+                .unwrap()
+        };
+        assert!(git(&["init", "-q"]).status.success());
+        for (path, contents) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        fs::write(dir.join("input.patch"), patch).unwrap();
+        let ok = git(&["apply", "--check", "input.patch"]).status.success();
+        let _ = fs::remove_dir_all(&dir);
+        ok
+    }
+
+    fn numbered_lines(n: usize) -> String {
+        (1..=n).map(|i| format!("line {i}\n")).collect()
+    }
+
+    // Hand-edited: the body is 7 old and 7 new lines, the header says otherwise
+    const MISCOUNTED: &str = "\
+diff --git a/f.txt b/f.txt
+--- a/f.txt
++++ b/f.txt
+@@ -2,3 +2,9 @@ section
+ line 2
+ line 3
+ line 4
+-line 5
++LINE 5
+ line 6
+ line 7
+ line 8
+";
+
+    #[test]
+    fn reflow_recounts_headers_until_git_apply_accepts_them() {
+        let original = numbered_lines(10);
+        assert!(!git_apply_check(
+            "reflow-before",
+            &[("f.txt", &original)],
+            MISCOUNTED
+        ));
+
+        let (files, hunks) = parse_unified_diff(MISCOUNTED).unwrap();
+        let order: Vec<usize> = (0..files.len()).collect();
+        let output = scratch_dir("reflow-out").join("out.patch");
+        let fixed = reflow_patch(&files, &hunks, &order, &output, None).unwrap();
+        assert_eq!(fixed, 1);
+        let out = fs::read_to_string(&output).unwrap();
+        assert!(out.contains("@@ -2,7 +2,7 @@ section\n"), "{out}");
+        assert!(!out.contains("-2,3 +2,9"));
+        assert!(git_apply_check(
+            "reflow-after",
+            &[("f.txt", &original)],
+            &out
+        ));
+        let _ = fs::remove_dir_all(output.parent().unwrap());
+    }
+
+    #[test]
+    fn recount_keeps_start_lines_and_section_text() {
+        let (files, hunks) = parse_unified_diff(MISCOUNTED).unwrap();
+        let (out, report) = render_patch(&files, &hunks, &[0], |_| true, true, None, None);
+        assert!(out.contains("@@ -2,7 +2,7 @@ section\n"));
+        assert_eq!(report.notes(), Vec::<String>::new());
+        // Without recounting the header goes out as it came in
+        let (out, _) = render_patch(&files, &hunks, &[0], |_| true, false, None, None);
+        assert!(out.contains("@@ -2,3 +2,9 @@ section\n"));
+    }
+
+    #[test]
+    fn recounted_headers_leave_out_counts_of_one() {
+        let body = ["-old".to_string(), "+new".to_string()];
+        assert_eq!(recount_header("@@ -3,4 +3,4 @@", &body), "@@ -3 +3 @@");
+        let added = ["+new".to_string()];
+        assert_eq!(
+            recount_header("@@ -3,0 +4,2 @@ f", &added),
+            "@@ -3,0 +4 @@ f"
+        );
+    }
+
+    /// An `App` over `patch` writing to a scratch output, with every hunk marked.
+    fn marked_app(name: &str, patch: &str) -> App {
+        let (files, hunks) = parse_unified_diff(patch).unwrap();
+        let output = scratch_dir(name).join("out.patch");
+        let mut app = App::new(
+            files,
+            hunks,
+            PathBuf::from("input.patch"),
+            output,
+            Keymap::new(&[]).unwrap(),
+        );
+        for h in &mut app.hunks {
+            h.marked = true;
+        }
+        app
+    }
+
+    const TWO_FILES: &str = "\
+diff --git a/a.txt b/a.txt
+--- a/a.txt
++++ b/a.txt
+@@ -1,3 +1,3 @@
+ one
+-two
++TWO
+ three
+diff --git a/b.txt b/b.txt
+--- a/b.txt
++++ b/b.txt
+@@ -1,2 +1,3 @@
+ one
++one and a half
+ two
+";
+
+    #[test]
+    fn excluding_every_change_line_drops_the_file() {
+        let mut app = marked_app("line-prune", TWO_FILES);
+        app.toggle_line_mode();
+        app.toggle_line_and_save().unwrap();
+        app.move_line_cursor(1);
+        app.toggle_line_and_save().unwrap();
+
+        let out = fs::read_to_string(&app.output_path).unwrap();
+        assert!(!out.contains("a.txt"), "{out}");
+        assert!(out.contains("diff --git a/b.txt b/b.txt\n"));
+        assert!(
+            app.status.contains("1 file(s) dropped from output"),
+            "{}",
+            app.status
+        );
+        let _ = fs::remove_dir_all(app.output_path.parent().unwrap());
+    }
+
+    #[test]
+    fn partly_excluded_hunk_is_recounted() {
+        let patch = "\
+diff --git a/a.txt b/a.txt
+--- a/a.txt
++++ b/a.txt
+@@ -1,3 +1,3 @@ fn main
+ a
+-b
+-x
++B
++c
+\\ No newline at end of file
+";
+        let mut app = marked_app("line-partial", patch);
+        app.toggle_line_mode();
+        // Keep `b` by excluding its removal, and leave out both added lines
+        app.toggle_line_and_save().unwrap();
+        app.move_line_cursor(2);
+        app.toggle_line_and_save().unwrap();
+        app.move_line_cursor(1);
+        app.toggle_line_and_save().unwrap();
+
+        let out = fs::read_to_string(&app.output_path).unwrap();
+        assert_eq!(
+            out,
+            "\
+diff --git a/a.txt b/a.txt
+--- a/a.txt
++++ b/a.txt
+@@ -1,3 +1,2 @@ fn main
+ a
+ b
+-x
+"
+        );
+        let _ = fs::remove_dir_all(app.output_path.parent().unwrap());
+    }
+
+    #[test]
+    fn order_file_puts_listed_files_first() {
+        // `a/two.txt` is a file in a directory named `a`, not a git side prefix
+        let patch: String = ["one.txt", "a/two.txt", "three.txt", "four.txt", "two.txt"]
+            .iter()
+            .map(|p| format!("diff --git a/{p} b/{p}\n--- a/{p}\n+++ b/{p}\n@@ -1 +1 @@\n-x\n+y\n"))
+            .collect();
+        let (files, _) = parse_unified_diff(&patch).unwrap();
+        let order_file = scratch_dir("order").join("order.txt");
+        fs::write(
+            &order_file,
+            "# review the tail first\n\nfour.txt\n  a/two.txt\n",
+        )
+        .unwrap();
+
+        let wanted = read_order_file(&order_file).unwrap();
+        assert_eq!(wanted, ["four.txt", "a/two.txt"]);
+        let order: Vec<String> = file_output_order(&files, &wanted)
+            .into_iter()
+            .map(|fidx| files[fidx].path())
+            .collect();
+        assert_eq!(
+            order,
+            ["four.txt", "a/two.txt", "one.txt", "three.txt", "two.txt"]
+        );
+        let _ = fs::remove_dir_all(order_file.parent().unwrap());
+    }
+
+    #[test]
+    fn deletion_without_preimage_is_flagged_apart_from_empty_file_deletion() {
+        let patch = include_str!("../fixtures/irreversible-delete.patch");
+        let (files, hunks) = parse_unified_diff(patch).unwrap();
+        let displays: Vec<(String, &str)> = hunks
+            .iter()
+            .map(|h| (files[h.file_idx].path(), h.display.as_str()))
+            .collect();
+        assert_eq!(displays.len(), 3);
+        assert_eq!(
+            displays[1..],
+            [
+                ("src/legacy.rs".to_string(), "(deletion, no preimage)"),
+                ("src/placeholder.rs".to_string(), "(empty file deleted)"),
+            ]
+        );
+        assert!(hunks[1].header_only && hunks[2].header_only);
+
+        let order: Vec<usize> = (0..files.len()).collect();
+        let (_, report) = render_patch(&files, &hunks, &order, |_| true, false, None, None);
+        assert_eq!(report.deletions_without_preimage, 1);
+        assert!(
+            report
+                .notes()
+                .iter()
+                .any(|n| n.starts_with("1 deletion(s) without preimage")),
+            "{:?}",
+            report.notes()
+        );
+
+        // Writing only the empty file's deletion is safe to apply
+        let (out, report) = render_patch(
+            &files,
+            &hunks,
+            &order,
+            |h| h.file_idx == 2,
+            false,
+            None,
+            None,
+        );
+        assert_eq!(report.deletions_without_preimage, 0);
+        assert!(report.notes().is_empty());
+        assert!(out.contains("index e69de29..0000000\n"));
+    }
+
+    // Changes at lines 5 and 25 of a 40-line file, as `git diff` writes them
+    const NEARBY: &str = "\
+diff --git a/f.txt b/f.txt
+--- a/f.txt
++++ b/f.txt
+@@ -2,7 +2,7 @@ line 1
+ line 2
+ line 3
+ line 4
+-line 5
++LINE 5
+ line 6
+ line 7
+ line 8
+@@ -22,7 +22,7 @@ line 21
+ line 22
+ line 23
+ line 24
+-line 25
++LINE 25
+ line 26
+ line 27
+ line 28
+";
+
+    fn regenerated(context: usize) -> String {
+        let (files, hunks) = parse_unified_diff(NEARBY).unwrap();
+        let original: Vec<String> = (1..=40).map(|i| format!("line {i}")).collect();
+        let regen = Regen {
+            context,
+            originals: HashMap::from([(0, original.as_slice())]),
+        };
+        let (out, report) = render_patch(&files, &hunks, &[0], |_| true, false, Some(&regen), None);
+        assert_eq!(report.context_kept, 0);
+        assert!(
+            git_apply_check(
+                &format!("regen-{context}"),
+                &[("f.txt", &numbered_lines(40))],
+                &out
+            ),
+            "{out}"
+        );
+        out
+    }
+
+    fn hunk_headers(patch: &str) -> Vec<&str> {
+        patch.lines().filter(|l| l.starts_with("@@")).collect()
+    }
+
+    #[test]
+    fn output_context_merges_hunks_closer_than_twice_the_context() {
+        // 19 lines apart: with 12 lines of context each side, the two hunks become one
+        assert_eq!(hunk_headers(&regenerated(12)), ["@@ -1,37 +1,37 @@"]);
+        assert_eq!(hunk_headers(&regenerated(30)), ["@@ -1,40 +1,40 @@"]);
+        // Far enough apart they stay separate, each with the heading for its new start
+        assert_eq!(
+            hunk_headers(&regenerated(1)),
+            ["@@ -4,3 +4,3 @@ line 3", "@@ -24,3 +24,3 @@ line 23"]
+        );
+    }
+
+    #[test]
+    fn saved_selection_loads_back_the_same_hunks() {
+        let patch: String = (1..=3)
+            .map(|i| format!("diff --git a/f{i} b/f{i}\n--- a/f{i}\n+++ b/f{i}\n@@ -1 +1 @@\n-x\n+y\n@@ -9 +9 @@\n-{i}\n+z\n"))
+            .collect();
+        let mut app = marked_app("selection", &patch);
+        for (i, h) in app.hunks.iter_mut().enumerate() {
+            h.marked = i % 2 == 0;
+        }
+        let manifest = app.output_path.with_file_name("selection.txt");
+        app.selection_path = Some(manifest.clone());
+        app.save().unwrap();
+        let ids = |app: &App| -> BTreeSet<String> {
+            app.hunks
+                .iter()
+                .filter(|h| h.marked)
+                .map(|h| hunk_id(&app.files[h.file_idx], h))
+                .collect()
+        };
+        let saved = ids(&app);
+        assert_eq!(saved.len(), 3);
+
+        // A hunk the input no longer has
+        let mut text = fs::read_to_string(&manifest).unwrap();
+        text.push_str("0123456789abcdef gone.txt @@ -1 +1 @@\n");
+        fs::write(&manifest, text).unwrap();
+
+        for h in &mut app.hunks {
+            h.marked = false;
+        }
+        assert_eq!(app.load_selection(&manifest).unwrap(), (3, 1));
+        assert_eq!(ids(&app), saved);
+        let _ = fs::remove_dir_all(manifest.parent().unwrap());
+    }
+
+    /// (path, hunk count) of each file in a parsed patch.
+    fn file_summary(files: &[FileDiff]) -> Vec<(String, usize)> {
+        files.iter().map(|f| (f.path(), f.hunks.len())).collect()
+    }
+
+    #[test]
+    fn parses_subversion_diffs() {
+        let (files, hunks) = parse_unified_diff(include_str!("../fixtures/svn.patch")).unwrap();
+        assert_eq!(
+            file_summary(&files),
+            [
+                ("src/main.c".to_string(), 2),
+                ("docs/README".to_string(), 1),
+                ("assets/logo.png".to_string(), 1),
+            ]
+        );
+        assert_eq!(files[0].old_path().as_deref(), Some("src/main.c"));
+        assert_eq!(
+            strip_timestamp("src/main.c    (revision 1412)"),
+            "src/main.c"
+        );
+        assert_eq!(
+            strip_timestamp("src/main.c    (working copy)"),
+            "src/main.c"
+        );
+
+        let logo = &hunks[files[2].hunks[0]];
+        assert!(logo.header_only);
+        assert_eq!(logo.display, "(binary)");
+
+        // The property block ends the hunk before it and stays with the file's headers
+        let readme = &hunks[files[1].hunks[0]];
+        assert_eq!(
+            readme.lines,
+            [
+                "-Main program.",
+                "+Main program, says hello.",
+                " Build with make."
+            ]
+        );
+        let (_, trailing) = files[1].split_headers();
+        assert_eq!(
+            trailing,
+            [
+                "",
+                "Property changes on: docs/README",
+                "___________________________________________________________________",
+                "Added: svn:keywords",
+                "## -0,0 +1 ##",
+                "+Id",
+                "\\ No newline at end of property",
+            ]
+        );
+        let order: Vec<usize> = (0..files.len()).collect();
+        let (out, _) = render_patch(
+            &files,
+            &hunks,
+            &order,
+            |h| h.file_idx == 1,
+            false,
+            None,
+            None,
+        );
+        assert!(
+            out.ends_with(" Build with make.\n\nProperty changes on: docs/README\n___________________________________________________________________\nAdded: svn:keywords\n## -0,0 +1 ##\n+Id\n\\ No newline at end of property\n"),
+            "{out}"
+        );
+    }
+
+    #[test]
+    fn parses_mercurial_diffs() {
+        let (files, hunks) = parse_unified_diff(include_str!("../fixtures/hg.patch")).unwrap();
+        assert_eq!(
+            file_summary(&files),
+            [
+                ("src/main.c".to_string(), 1),
+                ("docs/README".to_string(), 1),
+            ]
+        );
+        assert!(hunks.iter().all(|h| !h.header_only));
+        assert!(files[0].headers[0].starts_with("diff -r "));
+        assert_eq!(files[1].old_path().as_deref(), Some("docs/README"));
+        assert_eq!(
+            strip_timestamp("a/src/main.c    Tue Nov 14 22:13:20 2023 +0000"),
+            "a/src/main.c"
+        );
+        assert_eq!(hunks[1].lines.len(), 3);
+    }
+
+    fn one_hunk_section(path: &str, header: &str) -> String {
+        format!("diff --git a/{path} b/{path}\n--- a/{path}\n+++ b/{path}\n{header}\n-x\n+y\n")
+    }
+
+    #[test]
+    fn duplicate_file_entries_are_found_and_merged() {
+        let patch = [
+            one_hunk_section("a.txt", "@@ -20 +20 @@"),
+            one_hunk_section("b.txt", "@@ -1 +1 @@"),
+            one_hunk_section("a.txt", "@@ -3 +3 @@"),
+            one_hunk_section("a.txt", "@@ -40 +40 @@"),
+        ]
+        .concat();
+        let (mut files, mut hunks) = parse_unified_diff(&patch).unwrap();
+        let dupes = find_duplicate_files(&files);
+        assert_eq!(dupes.len(), 1);
+        assert_eq!(dupes[0].1, [0, 2, 3]);
+
+        assert!(merge_duplicate_files(&mut files, &mut hunks, &dupes).is_empty());
+        let starts: Vec<&str> = files[0]
+            .hunks
+            .iter()
+            .map(|&h| &hunks[h].header[..6])
+            .collect();
+        assert_eq!(starts, ["@@ -3 ", "@@ -20", "@@ -40"]);
+        assert!(files[2].hunks.is_empty() && files[3].hunks.is_empty());
+        assert!(files[0].hunks.iter().all(|&h| hunks[h].file_idx == 0));
+        let order: Vec<usize> = (0..files.len()).collect();
+        let (out, _) = render_patch(&files, &hunks, &order, |_| true, false, None, None);
+        assert_eq!(out.matches("diff --git a/a.txt").count(), 1);
+    }
+
+    #[test]
+    fn duplicate_entries_with_overlapping_hunks_are_not_merged() {
+        let patch = [
+            one_hunk_section("a.txt", "@@ -3,4 +3,4 @@"),
+            one_hunk_section("a.txt", "@@ -5 +5 @@"),
+        ]
+        .concat();
+        let (mut files, mut hunks) = parse_unified_diff(&patch).unwrap();
+        let dupes = find_duplicate_files(&files);
+        let overlapping = merge_duplicate_files(&mut files, &mut hunks, &dupes);
+        assert_eq!(overlapping, [files[0].file_label.clone()]);
+        assert_eq!(files[0].hunks.len(), 1);
+        assert_eq!(files[1].hunks.len(), 1);
+    }
+
+    #[test]
+    fn markdown_fence_outgrows_backticks_in_the_hunk() {
+        let patch = "\
+diff --git a/README.md b/README.md
+--- a/README.md
++++ b/README.md
+@@ -1,3 +1,3 @@
+ Example:
+-```sh
++````sh
+ make
+";
+        let (files, hunks) = parse_unified_diff(patch).unwrap();
+        let md = hunk_as_markdown(&files[0], &hunks[0]);
+        assert!(
+            md.starts_with("`README.md`\n\n`````diff\n@@ -1,3 +1,3 @@\n"),
+            "{md}"
+        );
+        assert!(md.ends_with("\n make\n`````\n"), "{md}");
+
+        let (files, hunks) = parse_unified_diff(MISCOUNTED).unwrap();
+        let md = hunk_as_markdown(&files[0], &hunks[0]);
+        assert!(
+            md.contains("\n```diff\n") && md.ends_with("\n```\n"),
+            "{md}"
+        );
+    }
+
+    #[test]
+    fn hunk_id_follows_path_and_body() {
+        let ids = |patch: &str| -> Vec<String> {
+            let (files, hunks) = parse_unified_diff(patch).unwrap();
+            hunks
+                .iter()
+                .map(|h| hunk_id(&files[h.file_idx], h))
+                .collect()
+        };
+        let first = ids(TWO_FILES);
+        assert_eq!(first, ids(TWO_FILES));
+        assert_ne!(first[0], first[1]);
+        // Same body under another path, and same path with another body
+        assert_ne!(ids(&TWO_FILES.replace("a.txt", "c.txt"))[0], first[0]);
+        assert_ne!(ids(&TWO_FILES.replace("+TWO", "+Two"))[0], first[0]);
+        assert_eq!(ids(&TWO_FILES.replace("+TWO", "+Two"))[1], first[1]);
+    }
+
+    #[test]
+    fn partly_applied_hunk_applies_the_rest_later() {
+        let patch = "\
+diff --git a/f.txt b/f.txt
+--- a/f.txt
++++ b/f.txt
+@@ -1,6 +1,6 @@
+ line 1
+-line 2
++LINE 2
+ line 3
+ line 4
+-line 5
++LINE 5
+ line 6
+";
+        let dir = scratch_dir("partial-apply");
+        assert!(
+            Command::new("git")
+                .args(["init", "-q"])
+                .current_dir(&dir)
+                .status()
+                .unwrap()
+                .success()
+        );
+        fs::write(dir.join("f.txt"), numbered_lines(6)).unwrap();
+        let session_path = dir.join("s.session");
+        let app_for = |dir: &Path| {
+            let mut app = marked_app("partial-apply-out", patch);
+            app.repo_root = dir.to_path_buf();
+            app.session = Some(Session::load(session_path.clone()).unwrap());
+            app.restore_session();
+            app
+        };
+
+        let mut app = app_for(&dir);
+        app.hunks[0].excluded.extend([5, 6]);
+        app.apply_current().unwrap();
+        assert_eq!(app.hunks[0].apply, ApplyState::NotApplied);
+        assert_eq!(app.hunks[0].applied_lines, BTreeSet::from([1, 2]));
+        assert!(app.status.contains("only in part"), "{}", app.status);
+        let tree = fs::read_to_string(dir.join("f.txt")).unwrap();
+        assert_eq!(tree, numbered_lines(6).replace("line 2", "LINE 2"));
+
+        // A later run knows which lines went in and sends only the rest
+        let mut app = app_for(&dir);
+        assert_eq!(app.hunks[0].applied_lines, BTreeSet::from([1, 2]));
+        app.apply_current().unwrap();
+        assert_eq!(app.hunks[0].apply, ApplyState::Applied(0), "{}", app.status);
+        assert!(app.hunks[0].applied_lines.is_empty());
+        let tree = fs::read_to_string(dir.join("f.txt")).unwrap();
+        assert_eq!(
+            tree,
+            numbered_lines(6)
+                .replace("line 2", "LINE 2")
+                .replace("line 5", "LINE 5")
+        );
+        let saved = fs::read_to_string(&session_path).unwrap();
+        assert!(!saved.contains("partial "), "{saved}");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unreliable_hunks_are_held_until_confirmed_one_save_at_a_time() {
+        let key = |c: char| Chord::from_event(crossterm::event::KeyEvent::from(KeyCode::Char(c)));
+        let mut app = marked_app("held-unreliable", TWO_FILES);
+        let output = app.output_path.clone();
+        app.hunks[0].damage = Some(Damage::Truncated);
+        app.hunks[1].marked = false;
+        app.save().unwrap();
+        assert!(!fs::read_to_string(&output).unwrap().contains("+TWO"));
+        assert!(app.status.contains("press y"), "{}", app.status);
+        assert_eq!(app.mode(), Mode::Confirm);
+
+        app.handle_key(key('y')).unwrap();
+        assert!(fs::read_to_string(&output).unwrap().contains("+TWO"));
+        assert_eq!(app.mode(), Mode::Normal);
+
+        // The confirmation covered only the hunk held then
+        app.hunks[1].damage = Some(Damage::Wrapped);
+        app.hunks[1].marked = true;
+        app.save().unwrap();
+        let out = fs::read_to_string(&output).unwrap();
+        assert!(
+            out.contains("+TWO") && !out.contains("+one and a half"),
+            "{out}"
+        );
+        assert_eq!(app.mode(), Mode::Confirm);
+
+        // Any other key dismisses the prompt and keeps its usual meaning
+        app.handle_key(key('j')).unwrap();
+        assert_eq!(app.mode(), Mode::Normal);
+        assert_eq!(app.cursor, 1);
+        assert!(!app.hunks[1].writable());
+    }
+
+    #[test]
+    fn preview_scroll_counts_wrapped_rows() {
+        let lines = vec![
+            Line::from("short"),
+            Line::from("x".repeat(25)),
+            Line::from("short"),
+        ];
+        assert_eq!(wrapped_rows(&lines, 0, 10), 0);
+        assert_eq!(wrapped_rows(&lines, 2, 10), 4);
+        assert_eq!(wrapped_rows(&lines, 9, 10), 5);
+        // Far past what a u16 holds: pinned, not wrapped around
+        let many = vec![Line::from("x"); 70_000];
+        assert_eq!(wrapped_rows(&many, 70_000, 10), u16::MAX);
+    }
+
+    #[test]
+    fn conflicts_flag_hunks_the_tree_no_longer_matches() {
+        let dir = scratch_dir("conflicts");
+        fs::write(dir.join("a.txt"), "one\ntwo\nthree\n").unwrap();
+        fs::write(dir.join("b.txt"), "one\nTWO\n").unwrap();
+        let mut app = marked_app("conflicts-out", TWO_FILES);
+        app.repo_root = dir.clone();
+        app.check_tree = true;
+        app.check_conflicts();
+        assert!(!app.hunks[0].conflict);
+        assert!(app.hunks[1].conflict);
+
+        // An edit on disk is picked up by the next check
+        fs::write(dir.join("a.txt"), "one\ntwo!\nthree\n").unwrap();
+        let later = SystemTime::now() + Duration::from_secs(5);
+        fs::File::options()
+            .write(true)
+            .open(dir.join("a.txt"))
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        app.check_conflicts();
+        assert!(app.hunks[0].conflict);
+
+        // Hunks applied from here don't count
+        app.hunks[0].apply = ApplyState::Applied(0);
+        app.tree_stamps.remove(&0);
+        app.check_conflicts();
+        assert!(!app.hunks[0].conflict);
+        assert!(app.hunks[1].conflict);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn clip_cuts_by_characters_and_counts_the_rest() {
+        assert_eq!(clip("+short", Some(10)), "+short");
+        assert_eq!(clip("+exactly10", Some(10)), "+exactly10");
+        assert_eq!(clip("+abcdefghijkl", Some(5)), "+abcd… (+8 chars)");
+        // Multi-byte characters count once and are never split
+        assert_eq!(clip("+ééééé", Some(3)), "+éé… (+3 chars)");
+        assert_eq!(clip(&"x".repeat(500), None).len(), 500);
+    }
+
+    #[test]
+    fn split_by_dir_writes_one_patch_per_top_level_directory() {
+        let patch: String = ["src/a.rs", "src/deep/b.rs", "tests/t.rs", "Makefile"]
+            .iter()
+            .map(|p| format!("diff --git a/{p} b/{p}\n--- a/{p}\n+++ b/{p}\n@@ -1 +1 @@\n-x\n+y\n"))
+            .collect();
+        let split = scratch_dir("split");
+        fs::write(split.join("notes.patch"), "not ours\n").unwrap();
+        let mut app = marked_app("split-out", &patch);
+        check_split_names(&app.files).unwrap();
+        app.split_dir = Some(split.clone());
+        app.save().unwrap();
+        let read = |name: &str| fs::read_to_string(split.join(name)).unwrap();
+        let paths = |name: &str| -> Vec<String> {
+            let (files, _) = parse_unified_diff(&read(name)).unwrap();
+            files.iter().map(|f| f.path()).collect()
+        };
+        assert_eq!(paths("src.patch"), ["src/a.rs", "src/deep/b.rs"]);
+        assert_eq!(paths("tests.patch"), ["tests/t.rs"]);
+        assert_eq!(paths("root.patch"), ["Makefile"]);
+
+        // A later run removes what the earlier one wrote and no longer applies
+        let mut app = marked_app("split-out", &patch);
+        app.hunks[2].marked = false;
+        app.split_dir = Some(split.clone());
+        app.split_written = read_split_manifest(&split);
+        app.save().unwrap();
+        assert!(!split.join("tests.patch").exists());
+        assert!(split.join("src.patch").exists());
+        assert_eq!(read("notes.patch"), "not ours\n");
+        assert_eq!(read(SPLIT_MANIFEST), "root\nsrc\n");
+
+        let clash =
+            "diff --git a/root/x b/root/x\n--- a/root/x\n+++ b/root/x\n@@ -1 +1 @@\n-x\n+y\n";
+        let (files, _) = parse_unified_diff(&format!("{patch}{clash}")).unwrap();
+        assert!(check_split_names(&files).is_err());
+        let _ = fs::remove_dir_all(&split);
+    }
+
+    #[test]
+    fn next_change_wraps_around_and_walks_the_whole_file_in_the_file_view() {
+        let patch = "\
+diff --git a/f.txt b/f.txt
+--- a/f.txt
++++ b/f.txt
+@@ -1,4 +1,4 @@
+-a
++A
+ b
+ c
+-d
++D
+@@ -10,2 +10,2 @@
+ j
+-k
++K
+";
+        let mut app = marked_app("next-change", patch);
+        let walk = |app: &mut App, n: usize| -> Vec<(usize, usize)> {
+            (0..n)
+                .map(|_| {
+                    app.focus_next_change();
+                    app.focus.unwrap()
+                })
+                .collect()
+        };
+        assert_eq!(walk(&mut app, 3), [(0, 0), (0, 4), (0, 0)]);
+        assert_eq!(app.status, "Change 1/2");
+        app.view = View::Files;
+        assert_eq!(walk(&mut app, 4), [(0, 4), (1, 1), (0, 0), (0, 4)]);
+        assert_eq!(app.status, "Change 2/3");
+    }
+
+    #[test]
+    fn report_lists_the_hunks_as_written() {
+        let patch = format!(
+            "{NEARBY}diff --git a/gone.txt b/gone.txt\ndeleted file mode 100644\nindex 0123456..0000000\n"
+        );
+        let (files, mut hunks) = parse_unified_diff(&patch).unwrap();
+        let original: Vec<String> = (1..=40).map(|i| format!("line {i}")).collect();
+        let report_for = |hunks: &[Hunk], context: usize| {
+            let regen = Regen {
+                context,
+                originals: HashMap::from([(0, original.as_slice())]),
+            };
+            let (out, report) =
+                render_patch(&files, hunks, &[0, 1], |_| true, false, Some(&regen), None);
+            report::render(
+                &files,
+                hunks,
+                &[0, 1],
+                |_| true,
+                &report.written,
+                None,
+                &out,
+            )
+        };
+        let selected = |text: &str| -> Vec<String> {
+            let start = text.find("==== Selected hunks").unwrap();
+            let end = text.find("==== Patch").unwrap();
+            text[start..end]
+                .lines()
+                .skip(1)
+                .filter(|l| !l.starts_with(' ') && !l.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+
+        // Regenerated with one line of context: the listing shows the new headers
+        let text = report_for(&hunks, 1);
+        assert_eq!(
+            selected(&text),
+            [
+                "f.txt  @@ -4,3 +4,3 @@ line 3",
+                "f.txt  @@ -24,3 +24,3 @@ line 23",
+                "gone.txt  (deletion, no preimage)",
+            ]
+        );
+        assert!(text.contains("3 of 3 hunk(s) selected, in 2 of 2 file(s): +2 -2"));
+        // Merged into one; a hunk left without changes is listed as such
+        hunks[1].excluded.extend([3, 4]);
+        let text = report_for(&hunks, 12);
+        assert_eq!(
+            selected(&text),
+            [
+                "f.txt  @@ -1,17 +1,17 @@",
+                "f.txt  @@ -22,7 +22,7 @@ line 21  (no changes left)",
+                "gone.txt  (deletion, no preimage)",
+            ]
+        );
+        assert!(text.contains(": +1 -1"));
+    }
+
+    #[test]
+    fn jumping_to_a_file_with_no_listed_hunk_says_so() {
+        let mut app = marked_app("jump", TWO_FILES);
+        app.jump_to_file(1);
+        assert_eq!(app.cursor, 1);
+        assert_eq!(app.status, "Jumped to a/b.txt → b/b.txt");
+        app.order.truncate(1);
+        app.cursor = 0;
+        app.jump_to_file(1);
+        assert_eq!(app.cursor, 0);
+        assert_eq!(app.status, "No hunk of a/b.txt → b/b.txt is listed here");
+    }
+
+    #[test]
+    fn anonymize_covers_the_report_and_split_names() {
+        let patch: String = ["secret/a.rs", "Makefile"]
+            .iter()
+            .map(|p| format!("diff --git a/{p} b/{p}\n--- a/{p}\n+++ b/{p}\n@@ -1 +1 @@\n-x\n+y\n"))
+            .collect();
+        let mut app = marked_app("anon-out", &patch);
+        let dir = app.output_path.parent().unwrap().to_path_buf();
+        app.anonymizer = Some(Anonymizer::new(&app.files));
+        app.split_dir = Some(dir.join("split"));
+        app.report_path = Some(dir.join("report.txt"));
+        app.save().unwrap();
+        let report = fs::read_to_string(dir.join("report.txt")).unwrap();
+        assert!(
+            !report.contains("secret") && !report.contains("Makefile"),
+            "{report}"
+        );
+        assert!(report.contains("file1  @@ -1 +1 @@"), "{report}");
+        assert!(dir.join("split/dir1.patch").exists());
+        assert!(dir.join("split/root.patch").exists());
+        assert!(!dir.join("split/secret.patch").exists());
+    }
+
+    #[test]
+    fn crlf_input_with_tabs_keeps_offsets_into_the_raw_text() {
+        let patch = "diff --git a/f.txt b/f.txt\r\n--- a/f.txt\r\n+++ b/f.txt\r\n@@ -1 +1 @@\r\n-\tx\r\n+\ty\r\ndiff --git a/run.sh b/run.sh\r\nold mode 100644\r\nnew mode 100755\r\n";
+        let (files, hunks) = parse_unified_diff(patch).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(hunks[0].lines, ["-    x", "+    y"]);
+        assert_eq!(files[0].headers[1], "--- a/f.txt");
+        for (hunk, first_line) in [
+            (&hunks[0], "@@ -1 +1 @@"),
+            (&hunks[1], "diff --git a/run.sh"),
+        ] {
+            let raw_line = patch.split("\r\n").nth(hunk.line_offset).unwrap();
+            assert!(raw_line.starts_with(first_line), "{raw_line}");
+            assert!(patch[hunk.byte_offset..].starts_with(first_line));
+        }
+        assert!(hunks[1].header_only);
+        assert_eq!((hunks[0].line_offset, hunks[1].line_offset), (3, 6));
+        assert_eq!(
+            hunks[0].byte_offset,
+            3 * 2 + "diff --git a/f.txt b/f.txt--- a/f.txt+++ b/f.txt".len()
+        );
+    }
 }
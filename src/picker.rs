@@ -0,0 +1,100 @@
+//! `F`: a fuzzy picker over the patch's file labels, for jumping to a file in patches too long
+//! to scroll through.
+
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+
+#[derive(Default)]
+pub struct FilePicker {
+    pub query: String,
+    /// Index into the current matches.
+    pub selected: usize,
+}
+
+/// A file matching the query, with the positions of the matched characters in its label.
+pub struct Match {
+    pub file_idx: usize,
+    pub positions: Vec<usize>,
+}
+
+impl FilePicker {
+    /// Files whose label matches the query, best first; every file, in order, for an empty query.
+    /// `labels` pairs file indices with their labels.
+    pub fn matches<'a>(&self, labels: impl Iterator<Item = (usize, &'a str)>) -> Vec<Match> {
+        let matcher = SkimMatcherV2::default().ignore_case();
+        let mut scored: Vec<(i64, Match)> = labels
+            .filter_map(|(file_idx, label)| {
+                let (score, positions) = matcher.fuzzy_indices(label, &self.query)?;
+                Some((
+                    score,
+                    Match {
+                        file_idx,
+                        positions,
+                    },
+                ))
+            })
+            .collect();
+        // Stable, so equal scores keep the patch order
+        scored.sort_by_key(|(score, _)| -score);
+        scored.into_iter().map(|(_, m)| m).collect()
+    }
+
+    pub fn move_by(&mut self, delta: isize, count: usize) {
+        if count == 0 {
+            self.selected = 0;
+            return;
+        }
+        self.selected = (self.selected as isize + delta).clamp(0, count as isize - 1) as usize;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LABELS: [&str; 4] = ["src/main.rs", "src/picker.rs", "readme.md", "tests/main.rs"];
+
+    fn matched(query: &str) -> Vec<usize> {
+        let picker = FilePicker {
+            query: query.to_string(),
+            selected: 0,
+        };
+        let labels = LABELS.iter().enumerate().map(|(i, l)| (i, *l));
+        picker.matches(labels).iter().map(|m| m.file_idx).collect()
+    }
+
+    #[test]
+    fn matches_rank_fuzzy_hits_and_keep_order_without_a_query() {
+        assert_eq!(matched(""), [0, 1, 2, 3]);
+        assert_eq!(matched("pick"), [1]);
+        assert_eq!(matched("README"), [2]);
+        assert!(matched("zzz").is_empty());
+        // Both main.rs files match; the tie keeps patch order
+        let mains = matched("main");
+        assert_eq!(mains, [0, 3]);
+
+        let picker = FilePicker {
+            query: "prs".into(),
+            selected: 0,
+        };
+        let m = picker.matches(std::iter::once((7, "src/picker.rs")));
+        assert_eq!(m[0].file_idx, 7);
+        assert_eq!(m[0].positions.len(), 3);
+    }
+
+    #[test]
+    fn move_by_stays_within_the_matches() {
+        let mut picker = FilePicker::default();
+        picker.move_by(-1, 3);
+        assert_eq!(picker.selected, 0);
+        picker.move_by(1, 3);
+        picker.move_by(5, 3);
+        assert_eq!(picker.selected, 2);
+        // The list shrank under the selection
+        picker.move_by(0, 1);
+        assert_eq!(picker.selected, 0);
+        picker.selected = 4;
+        picker.move_by(1, 0);
+        assert_eq!(picker.selected, 0);
+    }
+}
@@ -0,0 +1,255 @@
+//! `--output-context N`: rebuild hunks with exactly N lines of context taken from the original
+//! file, whatever context the input patch came with.
+
+use crate::source::{locate, old_side};
+use crate::{HunkRange, count_body, format_hunk_header, parse_hunk_header};
+
+/// Longest section heading git puts after the `@@`, in bytes.
+const HEADING_MAX: usize = 80;
+
+/// 0-based line a hunk side starts at; an empty side names the line *before* the hunk.
+fn start0(start: usize, len: usize) -> usize {
+    if len == 0 {
+        start
+    } else {
+        start.saturating_sub(1)
+    }
+}
+
+/// A body line with the old-file position it sits at and the input hunk it came from; `None`
+/// for context filled in from the original between two input hunks.
+struct Item<'a> {
+    kind: char,
+    pos: usize,
+    line: &'a str,
+    src: Option<usize>,
+}
+
+/// A hunk in the output, before its new-side start is known.
+struct Out {
+    old_start0: usize,
+    lines: Vec<String>,
+    tail: String,
+    // The input header of a hunk written as it came
+    kept: Option<String>,
+}
+
+/// Regenerate the hunks of one file (headers and bodies, exclusions already applied, in input
+/// order) with `n` context lines from `original`, its pre-image. As with `diff -U n`, changes
+/// more than `2n` context lines apart go in separate hunks and the rest share one, so hunks
+/// whose new context would touch or overlap are merged and long ones are split. Hunks that
+/// can't be placed in `original` are kept as they came; returns the output hunks and how many
+/// of those there were.
+pub fn file_with_context(
+    hunks: Vec<(String, Vec<String>)>,
+    original: &[String],
+    n: usize,
+) -> (Vec<(String, Vec<String>)>, usize) {
+    let mut out: Vec<Out> = Vec::new();
+    // (old-file position, old-side length, header, body) of each hunk found in `original`
+    let mut placed = Vec::new();
+    for (header, body) in hunks {
+        let found = parse_hunk_header(&header).and_then(|(range, _)| {
+            let old = old_side(&body);
+            let at = locate(original, &old, start0(range.old_start, range.old_len))?;
+            Some((at, old.len()))
+        });
+        match found {
+            Some((at, len)) => placed.push((at, len, header, body)),
+            None => out.push(kept(header, body)),
+        }
+    }
+    placed.sort_by_key(|p| p.0);
+    // Input hunks claiming the same lines can't be merged; keep the later ones as they are
+    let mut end = 0;
+    let (placed, overlapping): (Vec<_>, Vec<_>) = placed.into_iter().partition(|p| {
+        let fits = p.0 >= end;
+        if fits {
+            end = p.0 + p.1;
+        }
+        fits
+    });
+    let kept_count = out.len() + overlapping.len();
+    out.extend(overlapping.into_iter().map(|(_, _, h, b)| kept(h, b)));
+
+    // One run of items over the whole file, with the original's lines between the hunks
+    let mut items: Vec<Item> = Vec::new();
+    for (src, (at, _, _, body)) in placed.iter().enumerate() {
+        if let Some(prev) = items.last() {
+            let from = prev.pos + usize::from(prev.kind == ' ' || prev.kind == '-');
+            items.extend((from..*at).map(|pos| Item {
+                kind: ' ',
+                pos,
+                line: "",
+                src: None,
+            }));
+        }
+        let mut pos = *at;
+        for l in body {
+            let kind = l.chars().next().unwrap_or(' ');
+            items.push(Item {
+                kind,
+                pos,
+                line: l,
+                src: Some(src),
+            });
+            if kind == ' ' || kind == '-' {
+                pos += 1;
+            }
+        }
+    }
+    // A marker after context: the file's last line has no newline on either side
+    let no_eol = items
+        .windows(2)
+        .any(|w| w[0].kind == ' ' && w[1].kind == '\\');
+
+    let mut groups: Vec<(usize, usize)> = Vec::new();
+    for i in (0..items.len()).filter(|&i| matches!(items[i].kind, '+' | '-')) {
+        match groups.last_mut() {
+            Some((_, last))
+                if items[*last..i].iter().filter(|it| it.kind == ' ').count() <= 2 * n =>
+            {
+                *last = i
+            }
+            _ => groups.push((i, i)),
+        }
+    }
+    for (first, mut last) in groups {
+        if items.get(last + 1).is_some_and(|it| it.kind == '\\') {
+            last += 1;
+        }
+        let old_first = items[first].pos;
+        let old_end = items[..=last]
+            .iter()
+            .rev()
+            .find(|it| it.kind == ' ' || it.kind == '-')
+            .map_or(old_first, |it| it.pos + 1)
+            .max(old_first);
+        let pre = old_first.saturating_sub(n)..old_first;
+        let post = old_end..(old_end + n).min(original.len());
+        let start = pre.start;
+
+        let mut lines: Vec<String> = original[pre].iter().map(|l| format!(" {l}")).collect();
+        lines.extend(
+            items[first..=last]
+                .iter()
+                .map(|it| match (it.src, it.line) {
+                    (None, _) => format!(" {}", original[it.pos]),
+                    (Some(_), "") => " ".to_string(),
+                    (Some(_), l) => l.to_string(),
+                }),
+        );
+        let reaches_eof = !post.is_empty() && post.end == original.len();
+        lines.extend(original[post].iter().map(|l| format!(" {l}")));
+        if reaches_eof && no_eol {
+            lines.push("\\ No newline at end of file".to_string());
+        }
+
+        // The section heading is looked up again for the new start: the input's names a line
+        // above the old one, which may now be inside the hunk
+        out.push(Out {
+            old_start0: start,
+            lines,
+            tail: heading(&original[..start]),
+            kept: None,
+        });
+    }
+
+    // New-side starts follow from the hunks before, as only these get applied
+    out.sort_by_key(|o| o.old_start0);
+    let mut delta = 0isize;
+    let side_start = |s0: usize, len: usize| if len == 0 { s0 } else { s0 + 1 };
+    let hunks = out.into_iter().map(|o| {
+        let (old_len, new_len) = count_body(&o.lines);
+        let new_start0 = (o.old_start0 as isize + delta).max(0) as usize;
+        delta += new_len as isize - old_len as isize;
+        let header = o.kept.unwrap_or_else(|| {
+            let range = HunkRange {
+                old_start: side_start(o.old_start0, old_len),
+                old_len,
+                new_start: side_start(new_start0, new_len),
+                new_len,
+            };
+            format_hunk_header(range, &o.tail)
+        });
+        (header, o.lines)
+    });
+    (hunks.collect(), kept_count)
+}
+
+/// An input hunk written as it came, placed by its header's old start.
+fn kept(header: String, body: Vec<String>) -> Out {
+    let old_start0 = parse_hunk_header(&header).map_or(0, |(r, _)| start0(r.old_start, r.old_len));
+    Out {
+        old_start0,
+        lines: body,
+        tail: String::new(),
+        kept: Some(header),
+    }
+}
+
+/// The section heading for a hunk starting after `before`, by git's default rule: the last
+/// line that starts with a letter, `_` or `$`, cut to 80 bytes. Empty when there's none.
+fn heading(before: &[String]) -> String {
+    let Some(line) = before
+        .iter()
+        .rev()
+        .find(|l| l.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '$'))
+    else {
+        return String::new();
+    };
+    let mut cut = line.len().min(HEADING_MAX);
+    while !line.is_char_boundary(cut) {
+        cut -= 1;
+    }
+    format!(" {}", line[..cut].trim_end())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_hunks_get_their_heading_looked_up_again() {
+        let original: Vec<String> = (1..=30).map(|i| format!("line {i}")).collect();
+        let mut body: Vec<String> = (3..=27).map(|i| format!(" line {i}")).collect();
+        body[2] = "-line 5".to_string();
+        body[22] = "-line 25".to_string();
+        let hunk = ("@@ -3,25 +3,23 @@ fn main".to_string(), body);
+
+        let (out, kept) = file_with_context(vec![hunk], &original, 2);
+        assert_eq!(kept, 0);
+        let headers: Vec<&str> = out.iter().map(|(h, _)| h.as_str()).collect();
+        assert_eq!(
+            headers,
+            ["@@ -3,5 +3,4 @@ line 2", "@@ -23,5 +22,4 @@ line 22"]
+        );
+        assert_eq!(
+            out[1].1,
+            [" line 23", " line 24", "-line 25", " line 26", " line 27"]
+        );
+    }
+
+    #[test]
+    fn hunks_not_in_the_original_are_kept() {
+        let original: Vec<String> = (1..=10).map(|i| format!("line {i}")).collect();
+        let stray = (
+            "@@ -4,2 +4,2 @@".to_string(),
+            vec!["-elsewhere".to_string(), "+else".to_string()],
+        );
+        let (out, kept) = file_with_context(vec![stray.clone()], &original, 3);
+        assert_eq!(kept, 1);
+        assert_eq!(out, [stray]);
+    }
+
+    #[test]
+    fn heading_follows_gits_default_rule() {
+        let lines: Vec<String> = ["fn main() {   ", "    let x = 1;", "", "}"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(heading(&lines), " fn main() {");
+        assert_eq!(heading(&lines[1..]), "");
+        let long = vec!["x".repeat(100)];
+        assert_eq!(heading(&long).len(), 1 + HEADING_MAX);
+    }
+}
@@ -0,0 +1,139 @@
+//! `--report FILE`: one human-readable document for attaching to a review: overall and
+//! per-file stats, the selected hunks with a short preview each, then the filtered patch.
+
+use std::fmt::Write;
+
+use crate::anon::Anonymizer;
+use crate::{FileDiff, Hunk, change_counts, effective_hunk};
+
+/// The hunks written for one file, as (file index, [(header, body)]); a header-only entry has
+/// an empty header.
+pub type WrittenFile = (usize, Vec<(String, Vec<String>)>);
+
+/// Changed lines shown under each hunk in the list.
+const PREVIEW_LINES: usize = 3;
+
+fn section(out: &mut String, title: &str) {
+    let _ = writeln!(
+        out,
+        "\n==== {title} {}",
+        "=".repeat(60 - title.len().min(60))
+    );
+}
+
+/// Build the report. `include` picks the hunks that went into `patch`, the output just written,
+/// and `written` is what the same pass made of them (regenerated with `--output-context`).
+/// Paths are listed as `anon` wrote them in the patch.
+pub fn render(
+    files: &[FileDiff],
+    hunks: &[Hunk],
+    file_order: &[usize],
+    include: impl Fn(&Hunk) -> bool,
+    written: &[WrittenFile],
+    anon: Option<&Anonymizer>,
+    patch: &str,
+) -> String {
+    let paths: Vec<String> = files
+        .iter()
+        .map(|f| match anon {
+            Some(anon) => anon.path(&f.path()),
+            None => f.path(),
+        })
+        .collect();
+    let written_for = |fidx: usize| {
+        written
+            .iter()
+            .find(|(f, _)| *f == fidx)
+            .map_or(&[][..], |(_, bodies)| bodies.as_slice())
+    };
+    // Per file: (selected hunks, adds, dels) as they end up in the output
+    let stats: Vec<(usize, usize, usize)> = file_order
+        .iter()
+        .map(|&fidx| {
+            let selected = files[fidx]
+                .hunks
+                .iter()
+                .filter(|&&h| include(&hunks[h]))
+                .count();
+            let (adds, dels) = written_for(fidx)
+                .iter()
+                .map(|(_, lines)| change_counts(lines))
+                .fold((0, 0), |(a, d), (ha, hd)| (a + ha, d + hd));
+            (selected, adds, dels)
+        })
+        .collect();
+    let (selected, adds, dels) = stats
+        .iter()
+        .fold((0, 0, 0), |(n, a, d), s| (n + s.0, a + s.1, d + s.2));
+    let touched = stats.iter().filter(|s| s.0 > 0).count();
+
+    let mut out = String::from("patchers report\n");
+    section(&mut out, "Summary");
+    let _ = writeln!(
+        out,
+        "{selected} of {} hunk(s) selected, in {touched} of {} file(s): +{adds} -{dels}",
+        hunks.len(),
+        files.len()
+    );
+
+    section(&mut out, "Files");
+    let width = file_order
+        .iter()
+        .map(|&fidx| paths[fidx].chars().count())
+        .max()
+        .unwrap_or(0);
+    for (&fidx, &(n, a, d)) in file_order.iter().zip(&stats) {
+        let file = &files[fidx];
+        let _ = writeln!(
+            out,
+            "[{}] {:width$}  {n}/{} hunk(s)  +{a} -{d}",
+            if n > 0 { "x" } else { " " },
+            paths[fidx],
+            file.hunks.len(),
+        );
+    }
+
+    section(&mut out, "Selected hunks");
+    for &fidx in file_order {
+        let file = &files[fidx];
+        let selected: Vec<&Hunk> = file
+            .hunks
+            .iter()
+            .map(|&h| &hunks[h])
+            .filter(|h| include(h))
+            .collect();
+        for (header, lines) in written_for(fidx) {
+            if header.is_empty() {
+                let display = selected.iter().find(|h| h.header_only).map(|h| &h.display);
+                let _ = writeln!(out, "{}  {}", paths[fidx], display.map_or("", |d| d));
+                continue;
+            }
+            let _ = writeln!(out, "{}  {header}", paths[fidx]);
+            let changed: Vec<&String> = lines
+                .iter()
+                .filter(|l| l.starts_with('+') || l.starts_with('-'))
+                .collect();
+            for l in changed.iter().take(PREVIEW_LINES) {
+                let _ = writeln!(out, "    {l}");
+            }
+            if changed.len() > PREVIEW_LINES {
+                let _ = writeln!(
+                    out,
+                    "    … {} more changed line(s)",
+                    changed.len() - PREVIEW_LINES
+                );
+            }
+        }
+        // Left out of the patch altogether
+        for h in selected
+            .iter()
+            .filter(|h| effective_hunk(h, false).is_none())
+        {
+            let _ = writeln!(out, "{}  {}  (no changes left)", paths[fidx], h.header);
+        }
+    }
+
+    section(&mut out, "Patch");
+    out.push_str(patch);
+    out
+}
@@ -0,0 +1,148 @@
+//! Session state kept between runs (`--session FILE`): which hunks are marked and which were
+//! sent to `git apply`, keyed by hunk content hash so it survives regenerating the patch.
+
+use anyhow::{Context, Result};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use crate::ApplyState;
+
+pub struct Session {
+    path: PathBuf,
+    pub marked: HashSet<String>,
+    pub applied: HashMap<String, ApplyState>,
+    // Body lines of hunks applied only in part (with other lines excluded)
+    pub partial: HashMap<String, BTreeSet<usize>>,
+}
+
+impl Session {
+    /// Load the session file; a missing file starts an empty session.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let mut session = Session {
+            path,
+            marked: HashSet::new(),
+            applied: HashMap::new(),
+            partial: HashMap::new(),
+        };
+        let text = match fs::read_to_string(&session.path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(session),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read {}", session.path.display()));
+            }
+        };
+        for line in text.lines() {
+            match line.split_once(' ') {
+                Some(("marked", id)) => {
+                    session.marked.insert(id.to_string());
+                }
+                Some(("applied", rest)) => {
+                    // An offset follows the id when git had to shift the hunk
+                    let (id, offset) = rest.split_once(' ').unwrap_or((rest, "0"));
+                    let offset = offset.parse().unwrap_or(0);
+                    session
+                        .applied
+                        .insert(id.to_string(), ApplyState::Applied(offset));
+                }
+                Some(("failed", id)) => {
+                    session.applied.insert(id.to_string(), ApplyState::Failed);
+                }
+                Some(("partial", rest)) => {
+                    let (id, lines) = rest.split_once(' ').unwrap_or((rest, ""));
+                    let lines = lines.split(',').filter_map(|n| n.parse().ok()).collect();
+                    session.partial.insert(id.to_string(), lines);
+                }
+                _ => {}
+            }
+        }
+        Ok(session)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let mut out = String::from("# patchers session\n");
+        let mut marked: Vec<&String> = self.marked.iter().collect();
+        marked.sort();
+        for id in marked {
+            out.push_str(&format!("marked {id}\n"));
+        }
+        let mut applied: Vec<(&String, &ApplyState)> = self.applied.iter().collect();
+        applied.sort();
+        for (id, state) in applied {
+            match state {
+                ApplyState::Applied(0) => out.push_str(&format!("applied {id}\n")),
+                ApplyState::Applied(offset) => out.push_str(&format!("applied {id} {offset:+}\n")),
+                ApplyState::Failed => out.push_str(&format!("failed {id}\n")),
+                ApplyState::NotApplied => {}
+            }
+        }
+        let mut partial: Vec<(&String, &BTreeSet<usize>)> = self.partial.iter().collect();
+        partial.sort();
+        for (id, lines) in partial {
+            let lines: Vec<String> = lines.iter().map(usize::to_string).collect();
+            out.push_str(&format!("partial {id} {}\n", lines.join(",")));
+        }
+        fs::write(&self.path, out)
+            .with_context(|| format!("failed to write session {}", self.path.display()))
+    }
+}
+
+/// Write a selection manifest (`--save-selection`): one marked hunk per line, its id followed
+/// by the file and hunk header for whoever reads it.
+pub fn save_selection(path: &Path, hunks: &[(String, String)]) -> Result<()> {
+    let mut out = String::from("# patchers selection: <hunk id> <file> <hunk header>\n");
+    for (id, what) in hunks {
+        out.push_str(&format!("{id} {what}\n"));
+    }
+    fs::write(path, out).with_context(|| format!("failed to write selection {}", path.display()))
+}
+
+/// Read the hunk ids of a selection manifest (`--load-selection`).
+pub fn load_selection(path: &Path) -> Result<HashSet<String>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("failed to read selection {}", path.display()))?;
+    Ok(text
+        .lines()
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|l| l.split_whitespace().next())
+        .map(str::to_string)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_round_trips_marks_and_apply_results() {
+        let path = std::env::temp_dir().join(format!("patchers-{}-session", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut session = Session::load(path.clone()).unwrap();
+        assert!(session.marked.is_empty() && session.applied.is_empty());
+        session.marked.insert("aaaa".into());
+        for (id, state) in [
+            ("exact", ApplyState::Applied(0)),
+            ("below", ApplyState::Applied(3)),
+            ("above", ApplyState::Applied(-2)),
+            ("refused", ApplyState::Failed),
+        ] {
+            session.applied.insert(id.into(), state);
+        }
+        session
+            .partial
+            .insert("half".into(), BTreeSet::from([1, 4]));
+        session.save().unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("applied below +3\n"), "{text}");
+        assert!(text.contains("applied above -2\n"), "{text}");
+        assert!(text.contains("applied exact\n"), "{text}");
+        let loaded = Session::load(path.clone()).unwrap();
+        assert_eq!(loaded.marked, session.marked);
+        assert_eq!(loaded.applied, session.applied);
+        assert_eq!(loaded.partial, session.partial);
+        let _ = fs::remove_file(&path);
+    }
+}
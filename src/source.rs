@@ -0,0 +1,134 @@
+//! Original (pre-image) contents of the files a patch touches, for showing a hunk in the
+//! context of its whole file.
+//!
+//! Files are looked up under `--orig-dir` first, so patches from outside any git repository
+//! still get full context; then the pre-image blob named on the `index` header line is read
+//! from the repository at `--repo-root`. When neither has the file, callers fall back to
+//! showing the hunk alone.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::{FileDiff, git};
+
+/// Where an original file was found.
+#[derive(Debug, Clone)]
+pub enum Origin {
+    Dir(PathBuf),
+    Blob(String),
+}
+
+impl Origin {
+    pub fn describe(&self) -> String {
+        match self {
+            Origin::Dir(path) => path.display().to_string(),
+            Origin::Blob(id) => format!("git blob {id}"),
+        }
+    }
+}
+
+pub struct Original {
+    pub origin: Origin,
+    pub lines: Vec<String>,
+}
+
+pub struct Sources {
+    orig_dir: Option<PathBuf>,
+    repo_root: PathBuf,
+    // Keyed by file index; `None` once a lookup came up empty, so it isn't retried every frame
+    cache: HashMap<usize, Option<Original>>,
+}
+
+impl Sources {
+    pub fn new(orig_dir: Option<PathBuf>, repo_root: PathBuf) -> Self {
+        Self {
+            orig_dir,
+            repo_root,
+            cache: HashMap::new(),
+        }
+    }
+
+    /// The original contents of `file` (the `index`-th file of the patch), if they can be found.
+    pub fn original(&mut self, index: usize, file: &FileDiff) -> Option<&Original> {
+        self.cache
+            .entry(index)
+            .or_insert_with(|| lookup(self.orig_dir.as_deref(), &self.repo_root, file))
+            .as_ref()
+    }
+
+    /// An original already looked up with [`Sources::original`].
+    pub fn cached(&self, index: usize) -> Option<&Original> {
+        self.cache.get(&index)?.as_ref()
+    }
+}
+
+fn lookup(orig_dir: Option<&Path>, repo_root: &Path, file: &FileDiff) -> Option<Original> {
+    let from_dir = orig_dir.and_then(|dir| {
+        let path = dir.join(file.old_path()?);
+        let text = fs::read_to_string(&path).ok()?;
+        Some(Original {
+            origin: Origin::Dir(path),
+            lines: split_lines(&text),
+        })
+    });
+    from_dir.or_else(|| {
+        let id = file.old_blob()?;
+        let text = git::cat_blob(repo_root, &id).ok()?;
+        Some(Original {
+            origin: Origin::Blob(id),
+            lines: split_lines(&text),
+        })
+    })
+}
+
+/// Lines normalized the way the patch itself is read, so they compare equal to hunk lines.
+fn split_lines(text: &str) -> Vec<String> {
+    text.replace("\r\n", "\n")
+        .replace('\t', "    ")
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+/// Current contents of `file` in the working tree under `root`; `None` for new files and
+/// files that can't be read.
+pub fn working_tree(root: &Path, file: &FileDiff) -> Option<Vec<String>> {
+    let text = fs::read_to_string(root.join(file.old_path()?)).ok()?;
+    Some(split_lines(&text))
+}
+
+/// When and at what size `file` was last written in the working tree under `root`, to tell
+/// whether it needs reading again; `None` when it can't be read.
+pub fn tree_stamp(root: &Path, file: &FileDiff) -> Option<(SystemTime, u64)> {
+    let meta = fs::metadata(root.join(file.old_path()?)).ok()?;
+    Some((meta.modified().ok()?, meta.len()))
+}
+
+/// The lines a hunk body expects to find in the file: its context and `-` lines.
+pub fn old_side(body: &[String]) -> Vec<&str> {
+    body.iter()
+        .filter_map(|l| match l.chars().next() {
+            None => Some(""),
+            Some(' ' | '-') => Some(&l[1..]),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Where `needle` occurs in `lines`, preferring the match closest to `near` (0-based), as
+/// `git apply` does when a hunk has moved.
+pub fn locate(lines: &[String], needle: &[&str], near: usize) -> Option<usize> {
+    if needle.len() > lines.len() {
+        return None;
+    }
+    (0..=lines.len() - needle.len())
+        .filter(|&at| {
+            lines[at..at + needle.len()]
+                .iter()
+                .zip(needle)
+                .all(|(a, b)| a == b)
+        })
+        .min_by_key(|&at| at.abs_diff(near))
+}